@@ -0,0 +1,152 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::keys::parse_key;
+
+/// 修饰键, 左右各占一位
+const LCTRL: u8 = 0b0000_0001;
+const RCTRL: u8 = 0b0000_0010;
+const LSHIFT: u8 = 0b0000_0100;
+const RSHIFT: u8 = 0b0000_1000;
+const LALT: u8 = 0b0001_0000;
+const RALT: u8 = 0b0010_0000;
+const LWIN: u8 = 0b0100_0000;
+const RWIN: u8 = 0b1000_0000;
+
+const CTRL: u8 = LCTRL | RCTRL;
+const SHIFT: u8 = LSHIFT | RSHIFT;
+const ALT: u8 = LALT | RALT;
+const WIN: u8 = LWIN | RWIN;
+
+/// 修饰键的虚拟键码, 不分左右的键码按左侧处理
+fn modifier_bit(code: u32) -> Option<u8> {
+    match code {
+        0xA2 | 0x11 => Some(LCTRL),
+        0xA3 => Some(RCTRL),
+        0xA0 | 0x10 => Some(LSHIFT),
+        0xA1 => Some(RSHIFT),
+        0xA4 | 0x12 => Some(LALT),
+        0xA5 => Some(RALT),
+        0x5B => Some(LWIN),
+        0x5C => Some(RWIN),
+        _ => None,
+    }
+}
+
+/// 组合键中修饰键的名称, `Ctrl`等不区分左右
+fn parse_modifier(name: &str) -> Option<u8> {
+    let modifier = match name.to_ascii_lowercase().as_str() {
+        "ctrl" | "control" => CTRL,
+        "lctrl" => LCTRL,
+        "rctrl" => RCTRL,
+        "shift" => SHIFT,
+        "lshift" => LSHIFT,
+        "rshift" => RSHIFT,
+        "alt" => ALT,
+        "lalt" => LALT,
+        "ralt" => RALT,
+        "win" => WIN,
+        "lwin" => LWIN,
+        "rwin" => RWIN,
+        _ => return None,
+    };
+    Some(modifier)
+}
+
+/// 当前按下的修饰键
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default, Clone, Copy)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub fn press(&mut self, code: u32) {
+        if let Some(bit) = modifier_bit(code) {
+            self.0 |= bit;
+        }
+    }
+
+    pub fn release(&mut self, code: u32) {
+        if let Some(bit) = modifier_bit(code) {
+            self.0 &= !bit;
+        }
+    }
+}
+
+/// 是否为修饰键
+pub fn is_modifier(code: u32) -> bool {
+    modifier_bit(code).is_some()
+}
+
+/// 组合键, 如`Ctrl+Shift+T`、`Win+D`, 主键也可以是修饰键(如连按两次`Shift`)
+#[derive(Debug, Clone)]
+pub struct Chord {
+    pub name: String,
+    modifiers: u8,
+    key: u32,
+    /// 主键为修饰键时可以匹配的修饰键
+    key_modifiers: u8,
+}
+
+impl Chord {
+    /// 解析`+`连接的组合键, 最后一个为主键, 前面的都是修饰键
+    pub fn parse(name: &str, keys: &str) -> Result<Self> {
+        let mut parts: Vec<&str> = keys.split('+').map(str::trim).collect();
+        let mut key = parts.pop().unwrap_or_default().to_string();
+        //`Num+`这类名称本身带有`+`
+        if key.is_empty() {
+            key = format!("{}+", parts.pop().unwrap_or_default());
+        }
+        let (key, key_modifiers) = match parse_modifier(&key) {
+            Some(key_modifiers) => (0, key_modifiers),
+            None => (
+                parse_key(&key).ok_or_else(|| anyhow!("未知的按键: {key}"))?,
+                0,
+            ),
+        };
+        let mut modifiers = 0;
+        for part in parts {
+            modifiers |= parse_modifier(part).ok_or_else(|| anyhow!("未知的修饰键: {part}"))?;
+        }
+        Ok(Self {
+            name: name.to_string(),
+            modifiers,
+            key,
+            key_modifiers,
+        })
+    }
+
+    /// 按下`code`时的修饰键是否与组合键完全一致, 如`Ctrl+C`不匹配`Ctrl+Shift+C`
+    pub fn matches(&self, modifiers: &Modifiers, code: u32) -> bool {
+        //按下的是修饰键时, 比较其他修饰键时不包含它自己
+        let (key_matches, pressed) = match modifier_bit(code) {
+            Some(bit) => (self.key_modifiers & bit != 0, modifiers.0 & !bit),
+            None => (code == self.key, modifiers.0),
+        };
+        key_matches
+            && [CTRL, SHIFT, ALT, WIN].into_iter().all(|group| {
+                let required = self.modifiers & group;
+                let pressed = pressed & group;
+                if required == group {
+                    pressed != 0
+                } else {
+                    pressed == required
+                }
+            })
+    }
+}
+
+/// 默认统计的组合键, 名称与之前版本的计数名称一致
+pub fn default_chords() -> BTreeMap<String, String> {
+    [
+        ("CTRL + C", "Ctrl+C"),
+        ("CTRL + X", "Ctrl+X"),
+        ("CTRL + V", "Ctrl+V"),
+        ("CTRL + Z", "Ctrl+Z"),
+        ("CTRL + Y", "Ctrl+Y"),
+        ("CTRL + S", "Ctrl+S"),
+        ("ALT + TAB", "Alt+Tab"),
+    ]
+    .into_iter()
+    .map(|(name, keys)| (name.to_string(), keys.to_string()))
+    .collect()
+}
@@ -0,0 +1,129 @@
+#[cfg(test)]
+use std::sync::Arc;
+use std::{fmt, sync::Mutex};
+
+use chrono::{DateTime, FixedOffset, Local};
+
+#[cfg(test)]
+use crate::counter::Counter;
+
+/// 时钟
+///
+/// 返回的时间带有当地的时区偏移, 计时使用其时间戳, 跨天判断使用其当地日期
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<FixedOffset>;
+}
+
+impl fmt::Debug for dyn Clock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Clock({})", self.now())
+    }
+}
+
+/// 系统时钟
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        Local::now().into()
+    }
+}
+
+/// 手动控制的时钟, 用于回放事件以及模拟跨天、时区变化和夏令时切换
+///
+/// 设置的时间可以带不同的时区偏移, 时间点不变而偏移改变即相当于切换了时区
+pub struct ManualClock {
+    now: Mutex<DateTime<FixedOffset>>,
+}
+
+impl ManualClock {
+    pub fn new(now: DateTime<FixedOffset>) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<FixedOffset>) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// 测试用: 解析RFC 3339格式的时间
+#[cfg(test)]
+pub fn parse_time(text: &str) -> DateTime<FixedOffset> {
+    DateTime::parse_from_rfc3339(text).unwrap()
+}
+
+/// 测试用: 时钟从`start`开始的计数器, 返回的时钟用于推进时间
+#[cfg(test)]
+pub fn manual_counter(start: &str) -> (Counter, Arc<ManualClock>) {
+    let clock = Arc::new(ManualClock::new(parse_time(start)));
+    (Counter::with_clock(clock.clone()), clock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{manual_counter, parse_time, ManualClock};
+    use crate::counter::{Counter, Event, KeyEvent};
+
+    /// 在`now`时按下并松开A键
+    fn press(counter: &mut Counter, clock: &ManualClock, now: &str) {
+        clock.set(parse_time(now));
+        counter.recv(Event::KeyEvent(KeyEvent::KeyPress(0x41)));
+        counter.recv(Event::KeyEvent(KeyEvent::KeyUp(0x41)));
+    }
+
+    fn keystrokes(maps: &std::collections::HashMap<String, u128>) -> u128 {
+        maps.get("keystrokes").copied().unwrap_or(0)
+    }
+
+    #[test]
+    fn midnight_rollover() {
+        let (mut counter, clock) = manual_counter("2024-03-30T23:59:00+08:00");
+        press(&mut counter, &clock, "2024-03-30T23:59:59+08:00");
+        press(&mut counter, &clock, "2024-03-31T00:00:01+08:00");
+
+        assert_eq!(counter.today.date, "2024-03-31");
+        assert_eq!(keystrokes(&counter.today.maps), 1);
+        assert_eq!(keystrokes(&counter.history.days["2024-03-30"].maps), 1);
+        assert_eq!(keystrokes(&counter.maps), 2);
+    }
+
+    #[test]
+    fn dst_offset_change() {
+        //美国东部时间夏令时开始, 01:59之后的一分钟是03:00
+        let (mut counter, clock) = manual_counter("2024-03-10T01:00:00-05:00");
+        press(&mut counter, &clock, "2024-03-10T01:59:30-05:00");
+        press(&mut counter, &clock, "2024-03-10T03:00:30-04:00");
+
+        assert_eq!(counter.today.date, "2024-03-10");
+        assert!(counter.history.days.is_empty());
+        let hours = &counter.today.timeline.hours["keystrokes"];
+        assert_eq!((hours[1], hours[2], hours[3]), (1, 0, 1));
+        //两次按键只间隔一分钟, 计入活跃时间
+        assert_eq!(counter.maps.get("active_time"), Some(&60));
+    }
+
+    #[test]
+    fn back_to_earlier_date() {
+        let (mut counter, clock) = manual_counter("2024-01-02T00:00:00+08:00");
+        press(&mut counter, &clock, "2024-01-02T00:30:00+08:00");
+        //切换到西八区, 当地日期回到前一天
+        press(&mut counter, &clock, "2024-01-01T08:40:00-08:00");
+        assert_eq!(counter.today.date, "2024-01-01");
+        //切换回东八区, 之前的日期再次结束时与已归档的计数合并
+        press(&mut counter, &clock, "2024-01-02T01:00:00+08:00");
+        press(&mut counter, &clock, "2024-01-03T09:00:00+08:00");
+
+        assert_eq!(counter.today.date, "2024-01-03");
+        assert_eq!(keystrokes(&counter.history.days["2024-01-01"].maps), 1);
+        assert_eq!(keystrokes(&counter.history.days["2024-01-02"].maps), 2);
+        assert_eq!(keystrokes(&counter.maps), 4);
+    }
+}
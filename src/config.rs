@@ -0,0 +1,376 @@
+use anyhow::{anyhow, Result};
+use raqote::{DrawOptions, SolidSource, Source, StrokeStyle};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::PathBuf, time::SystemTime};
+
+use crate::{
+    chord::default_chords,
+    counter::{DrawConfig, KEY_LIST},
+    i18n::Language,
+    theme::{builtin_theme, load_theme, system_dark_mode, CUSTOM_THEME},
+    tools::get_app_dir,
+};
+
+/// 首次运行时写入的配置文件, 列出所有可用的配置项
+const DEFAULT_CONFIG: &str = r##"# 按键统计配置文件, 修改后自动生效
+
+# 界面语言: zh-CN(简体中文)、en(English)
+language = "zh-CN"
+
+# 鼠标DPI, 设置后鼠标移动距离换算为米显示
+# mouse_dpi = 800
+
+# 超过这个时间(秒)没有任何输入视为离开, 不计入活跃时间
+idle_time = 60
+
+# 按键序列相邻两步之间的最长间隔(毫秒)
+sequence_timeout = 1000
+
+# 计数页显示的指标, 按顺序排列, 不设置时显示所有内置指标
+# 可以是内置指标、组合键和按键序列的名称, 也可以用key:按键名称显示单个按键的次数
+# 内置指标: mouse_left_click、mouse_right_click、mouse_double_click、mouse_wheel、mouse_move、
+# mouse_distance、keystrokes、key_presses、key_repeats、backspace、enter、esc、delete、tab、active_time
+# metrics = ["keystrokes", "mouse_left_click", "CTRL + C", "Ctrl+Shift+T", "key:F5", "active_time"]
+
+# 计数页每行显示的数量, 行数根据指标数量计算, 0为根据窗口大小自动计算
+columns = 6
+
+# 两次左击间隔小于double_click_time毫秒, 且位置相差小于double_click_distance像素算作双击
+double_click_time = 500
+double_click_distance = 4
+
+# 滚轮和鼠标移动计数的最短间隔(毫秒), 间隔内的多次滚动或移动只计一次
+wheel_interval = 800
+move_interval = 800
+
+# 两次按键间隔超过这个时间(毫秒)视为停止打字, 空闲时间不计入打字速度
+typing_idle_time = 5000
+
+# 是否统计每5分钟的计数(导出的CSV中包含), 开启后保存的数据会随天数明显增大
+five_minute_buckets = false
+
+# 自动保存间隔(秒), 两次保存之间的计数增量记录在增量日志中, 程序异常退出后启动时自动恢复
+autosave_interval = 60
+
+# 备份间隔(分钟)和保留的备份数量, 备份保存在程序数据目录的backups文件夹中, 数量为0时不备份
+backup_interval = 60
+backup_count = 24
+
+# 字体, 使用第一个已安装的字体
+fonts = ["微软雅黑", "Tahoma"]
+label_font_size = 20
+font_size = 24
+
+# 主题: dark(深色)、light(浅色)、high-contrast(高对比度)、custom(使用下面的[colors]),
+# 或者themes目录中主题文件的名称(不含.toml), 主题文件的格式与[colors]相同
+theme = "dark"
+
+# 跟随系统的深色/浅色模式(仅Windows), 开启后忽略theme
+follow_system_theme = false
+
+# 统计的组合键, 格式为 名称 = "修饰键+按键"
+# 修饰键: Ctrl、Shift、Alt、Win, 加L/R前缀区分左右(如LCtrl), 修饰键必须完全一致才计数
+# 按键名称与导出的按键名称相同, 如A、F5、Enter、Delete、Tab, 也可以写16进制键码如0x41
+[chords]
+"CTRL + C" = "Ctrl+C"
+"CTRL + X" = "Ctrl+X"
+"CTRL + V" = "Ctrl+V"
+"CTRL + Z" = "Ctrl+Z"
+"CTRL + Y" = "Ctrl+Y"
+"CTRL + S" = "Ctrl+S"
+"ALT + TAB" = "Alt+Tab"
+# "Ctrl+Shift+T" = "Ctrl+Shift+T"
+# "Win+D" = "Win+D"
+# "Ctrl+Alt+Del" = "Ctrl+Alt+Delete"
+
+# 统计的按键序列, 每一步为一个按键或组合键, 用空格分隔, 单独按下的修饰键不会打断序列
+# 相邻两步超过sequence_timeout毫秒则重新开始匹配
+[sequences]
+# "vim :w" = "Esc Shift+; W Enter"
+# "VS Code 注释" = "Ctrl+K Ctrl+C"
+# "双击Shift" = "Shift Shift"
+
+# 自定义主题的颜色, 格式为#RRGGBB或#AARRGGBB
+[colors]
+background = "#1a212c"
+border = "#707070"
+label = "#adadad"
+text = "#f1f1f1"
+button_background = "#707070"
+button_text = "#cdcdcd"
+"##;
+
+/// 界面颜色, 默认为深色主题
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct Colors {
+    pub background: String,
+    pub border: String,
+    pub label: String,
+    pub text: String,
+    pub button_background: String,
+    pub button_text: String,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            background: "#1a212c".to_string(),
+            border: "#707070".to_string(),
+            label: "#adadad".to_string(),
+            text: "#f1f1f1".to_string(),
+            button_background: "#707070".to_string(),
+            button_text: "#cdcdcd".to_string(),
+        }
+    }
+}
+
+/// 解析#RRGGBB或#AARRGGBB格式的颜色
+fn parse_color(color: &str) -> Option<SolidSource> {
+    let hex = color.strip_prefix('#')?;
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let alpha = match hex.len() {
+        6 => 0xFF,
+        8 => (value >> 24) as u8,
+        _ => return None,
+    };
+    Some(SolidSource::from_unpremultiplied_argb(
+        alpha,
+        (value >> 16) as u8,
+        (value >> 8) as u8,
+        value as u8,
+    ))
+}
+
+/// 解析颜色, 格式错误时使用默认颜色
+fn color_source(color: &str, default: &str) -> Source<'static> {
+    let color = parse_color(color).unwrap_or_else(|| {
+        eprintln!("颜色格式错误: {color}");
+        parse_color(default).unwrap()
+    });
+    Source::Solid(color)
+}
+
+/// 用户配置, 保存在程序目录的config.toml中
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// 界面语言
+    pub language: Language,
+    /// 鼠标DPI(每英寸移动的像素数)
+    pub mouse_dpi: Option<f64>,
+    /// 空闲时间(秒)
+    pub idle_time: i64,
+    /// 组合键名称和按键
+    pub chords: BTreeMap<String, String>,
+    /// 按键序列相邻两步之间的最长间隔(毫秒)
+    pub sequence_timeout: i64,
+    /// 按键序列名称和每一步的按键
+    pub sequences: BTreeMap<String, String>,
+    /// 计数页显示的指标
+    pub metrics: Vec<String>,
+    /// 计数页每行显示的数量, 0为自动
+    pub columns: usize,
+    /// 双击的最长间隔(毫秒)
+    pub double_click_time: i64,
+    /// 双击的最大距离(像素)
+    pub double_click_distance: i32,
+    /// 滚轮计数的最短间隔(毫秒)
+    pub wheel_interval: i64,
+    /// 鼠标移动计数的最短间隔(毫秒)
+    pub move_interval: i64,
+    /// 停止打字的按键间隔(毫秒)
+    pub typing_idle_time: i64,
+    /// 是否统计每5分钟的计数
+    pub five_minute_buckets: bool,
+    /// 自动保存间隔(秒)
+    pub autosave_interval: i64,
+    /// 备份间隔(分钟)
+    pub backup_interval: i64,
+    /// 保留的备份数量
+    pub backup_count: usize,
+    /// 字体名称, 按顺序选择
+    pub fonts: Vec<String>,
+    pub label_font_size: f32,
+    pub font_size: f32,
+    /// 主题名称
+    pub theme: String,
+    pub follow_system_theme: bool,
+    /// 自定义主题的颜色
+    pub colors: Colors,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            language: Language::default(),
+            mouse_dpi: None,
+            idle_time: 60,
+            chords: default_chords(),
+            sequence_timeout: 1000,
+            sequences: BTreeMap::new(),
+            metrics: KEY_LIST.iter().map(|name| name.to_string()).collect(),
+            columns: 6,
+            double_click_time: 500,
+            double_click_distance: 4,
+            wheel_interval: 800,
+            move_interval: 800,
+            typing_idle_time: 5000,
+            five_minute_buckets: false,
+            autosave_interval: 60,
+            backup_interval: 60,
+            backup_count: 24,
+            fonts: vec!["微软雅黑".to_string(), "Tahoma".to_string()],
+            label_font_size: 20.,
+            font_size: 24.,
+            theme: "dark".to_string(),
+            follow_system_theme: false,
+            colors: Colors::default(),
+        }
+    }
+}
+
+impl Config {
+    /// 检查数值范围, 为0或负数的间隔会使活跃时间、打字和重复检测失效, 或者每帧都保存一次
+    pub fn validate(&self) -> Result<()> {
+        let positive = [
+            ("idle_time", self.idle_time),
+            ("sequence_timeout", self.sequence_timeout),
+            ("typing_idle_time", self.typing_idle_time),
+            ("autosave_interval", self.autosave_interval),
+            ("backup_interval", self.backup_interval),
+        ];
+        if let Some((name, value)) = positive.iter().find(|(_, value)| *value <= 0) {
+            return Err(anyhow!("{name}必须大于0, 当前为{value}"));
+        }
+        let non_negative = [
+            ("double_click_time", self.double_click_time),
+            ("double_click_distance", self.double_click_distance as i64),
+            ("wheel_interval", self.wheel_interval),
+            ("move_interval", self.move_interval),
+        ];
+        if let Some((name, value)) = non_negative.iter().find(|(_, value)| *value < 0) {
+            return Err(anyhow!("{name}不能小于0, 当前为{value}"));
+        }
+        for (name, size) in [
+            ("label_font_size", self.label_font_size),
+            ("font_size", self.font_size),
+        ] {
+            if size.is_nan() || size <= 0. {
+                return Err(anyhow!("{name}必须大于0, 当前为{size}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// 当前主题的颜色, 跟随系统时根据系统设置选择深色或浅色主题
+    pub fn theme_colors(&self) -> Colors {
+        if self.follow_system_theme {
+            if let Some(dark) = system_dark_mode() {
+                return builtin_theme(if dark { "dark" } else { "light" }).unwrap_or_default();
+            }
+        }
+        if self.theme == CUSTOM_THEME {
+            return self.colors.clone();
+        }
+        load_theme(&self.theme).unwrap_or_else(|err| {
+            eprintln!("加载主题{}失败: {err}", self.theme);
+            Colors::default()
+        })
+    }
+
+    /// 根据主题和字号生成绘制配置
+    pub fn draw_config(&self) -> DrawConfig<'static> {
+        let colors = &self.theme_colors();
+        let defaults = Colors::default();
+        DrawConfig {
+            background: color_source(&colors.background, &defaults.background),
+            border_color: color_source(&colors.border, &defaults.border),
+            label_color: color_source(&colors.label, &defaults.label),
+            text_color: color_source(&colors.text, &defaults.text),
+            button_background: color_source(
+                &colors.button_background,
+                &defaults.button_background,
+            ),
+            button_text_color: color_source(&colors.button_text, &defaults.button_text),
+            stroke_style: StrokeStyle {
+                width: 1.,
+                ..Default::default()
+            },
+            draw_options: DrawOptions::default(),
+            lable_font_size: self.label_font_size,
+            font_size: self.font_size,
+        }
+    }
+}
+
+pub fn get_config_path() -> PathBuf {
+    let mut app_dir = get_app_dir().unwrap_or(PathBuf::from("./"));
+    app_dir.push("config.toml");
+    app_dir
+}
+
+/// 配置文件的修改时间, 用于检查配置文件是否变化
+pub fn config_modified_time() -> Option<SystemTime> {
+    std::fs::metadata(get_config_path())
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// 修改配置文件中一项顶层配置, 保留文件中的注释, `value`为toml格式的值
+pub fn write_config_value(key: &str, value: &str) -> Result<()> {
+    let path = get_config_path();
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|_| DEFAULT_CONFIG.to_string());
+    let line = format!("{key} = {value}");
+    let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+    //顶层配置必须在第一个[表]之前
+    let end = lines
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let found = lines[..end].iter().position(|line| {
+        line.split_once('=')
+            .is_some_and(|(name, _)| name.trim() == key && !name.trim_start().starts_with('#'))
+    });
+    match found {
+        Some(index) => lines[index] = line,
+        None => lines.insert(end, line),
+    }
+    std::fs::write(path, lines.join("\n") + "\n")?;
+    Ok(())
+}
+
+/// 读取配置文件, 文件不存在时写入默认配置
+pub fn read_config() -> Result<Config> {
+    let path = get_config_path();
+    if !path.exists() {
+        std::fs::write(&path, DEFAULT_CONFIG)?;
+    }
+    let config: Config = toml::from_str(&std::fs::read_to_string(path)?)?;
+    config.validate()?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, DEFAULT_CONFIG};
+
+    #[test]
+    fn default_config_is_valid() {
+        let config: Config = toml::from_str(DEFAULT_CONFIG).unwrap();
+        assert!(config.validate().is_ok());
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        let parse = |text: &str| toml::from_str::<Config>(text).unwrap().validate();
+        assert!(parse("autosave_interval = 0").is_err());
+        assert!(parse("idle_time = -1").is_err());
+        assert!(parse("typing_idle_time = 0").is_err());
+        assert!(parse("sequence_timeout = 0").is_err());
+        assert!(parse("wheel_interval = -5").is_err());
+        assert!(parse("font_size = 0.0").is_err());
+        assert!(parse("wheel_interval = 0").is_ok());
+    }
+}
@@ -0,0 +1,212 @@
+use font_kit::font::Font;
+use raqote::{DrawTarget, PathBuilder, Point as PointF, Source, StrokeStyle};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    counter::DrawConfig,
+    heatmap::heat_color,
+    i18n::tr_args,
+    tools::{draw_text, measure_text},
+};
+
+/// 连续删除的统计, 连续按下2次以上Backspace或Delete(中间没有其他按键)算一次连续删除
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct CorrectionStats {
+    /// 连续删除的次数
+    pub bursts: u64,
+    /// 连续删除中的按键总数
+    pub burst_keys: u64,
+    /// 最长一次连续删除的按键数
+    pub longest_burst: u64,
+}
+
+impl CorrectionStats {
+    /// `run`为当前连续删除的按键数(包含本次)
+    pub fn add(&mut self, run: u64) {
+        match run {
+            0 | 1 => return,
+            2 => {
+                self.bursts += 1;
+                self.burst_keys += 2;
+            }
+            _ => self.burst_keys += 1,
+        }
+        self.longest_burst = self.longest_burst.max(run);
+    }
+
+    pub fn merge(&mut self, other: CorrectionStats) {
+        self.bursts += other.bursts;
+        self.burst_keys += other.burst_keys;
+        self.longest_burst = self.longest_burst.max(other.longest_burst);
+    }
+
+    /// 平均每次连续删除的按键数
+    pub fn average_burst(&self) -> f64 {
+        if self.bursts > 0 {
+            self.burst_keys as f64 / self.bursts as f64
+        } else {
+            0.
+        }
+    }
+}
+
+/// 绘制每天纠错率的折线图, `days`为(日期, 纠错率)
+#[allow(clippy::too_many_arguments)]
+pub fn draw_trend(
+    dt: &mut DrawTarget,
+    font: &Font,
+    draw_config: &DrawConfig,
+    days: &[(String, f64)],
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+) {
+    let mut pb = PathBuilder::new();
+    pb.rect(x, y, width, height);
+    dt.stroke(
+        &pb.finish(),
+        &draw_config.border_color,
+        &draw_config.stroke_style,
+        &draw_config.draw_options,
+    );
+
+    let point_size = draw_config.lable_font_size * 0.8;
+    let max = days.iter().map(|(_, ratio)| *ratio).fold(0., f64::max);
+    let title = tr_args("correction.trend", &[("max", &format!("{:.1}%", max * 100.))]);
+    draw_text(
+        dt,
+        font,
+        point_size,
+        &title,
+        PointF::new(x + 8., y + point_size + 4.),
+        &draw_config.label_color,
+        &draw_config.draw_options,
+    );
+    let (Some((first, _)), Some((last, _))) = (days.first(), days.last()) else {
+        return;
+    };
+    draw_text(
+        dt,
+        font,
+        point_size,
+        first,
+        PointF::new(x + 8., y + height - 6.),
+        &draw_config.label_color,
+        &draw_config.draw_options,
+    );
+    let last_width = measure_text(font, point_size, last).x;
+    draw_text(
+        dt,
+        font,
+        point_size,
+        last,
+        PointF::new(x + width - 8. - last_width, y + height - 6.),
+        &draw_config.label_color,
+        &draw_config.draw_options,
+    );
+
+    //标题和日期之间的区域画折线
+    let top = y + point_size + 14.;
+    let bottom = y + height - point_size - 12.;
+    let left = x + 20.;
+    let right = x + width - 20.;
+    let step = if days.len() > 1 {
+        (right - left) / (days.len() - 1) as f32
+    } else {
+        0.
+    };
+    let point = |index: usize, ratio: f64| {
+        let ratio = if max > 0. { ratio / max } else { 0. };
+        PointF::new(
+            left + step * index as f32,
+            bottom - (bottom - top) * ratio as f32,
+        )
+    };
+    let mut pb = PathBuilder::new();
+    for (index, (_, ratio)) in days.iter().enumerate() {
+        let p = point(index, *ratio);
+        if index == 0 {
+            pb.move_to(p.x, p.y);
+        } else {
+            pb.line_to(p.x, p.y);
+        }
+    }
+    dt.stroke(
+        &pb.finish(),
+        &draw_config.text_color,
+        &StrokeStyle {
+            width: 2.,
+            ..Default::default()
+        },
+        &draw_config.draw_options,
+    );
+    for (index, (_, ratio)) in days.iter().enumerate() {
+        let p = point(index, *ratio);
+        let heat = if max > 0. { (ratio / max) as f32 } else { 0. };
+        dt.fill_rect(
+            p.x - 3.,
+            p.y - 3.,
+            6.,
+            6.,
+            &Source::Solid(heat_color(heat)),
+            &draw_config.draw_options,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        clock::manual_counter,
+        counter::{correction_ratio, Counter, Event, KeyEvent},
+    };
+
+    const VK_BACKSPACE: u32 = 0x08;
+    const VK_DELETE: u32 = 0x2E;
+    const VK_LCONTROL: u32 = 162;
+
+    /// 依次按下并松开`codes`中的按键
+    fn press(codes: &[u32]) -> Counter {
+        let (mut counter, _) = manual_counter("2024-01-01T10:00:00+08:00");
+        for code in codes {
+            counter.recv(Event::KeyEvent(KeyEvent::KeyPress(*code)));
+            counter.recv(Event::KeyEvent(KeyEvent::KeyUp(*code)));
+        }
+        counter
+    }
+
+    #[test]
+    fn burst_threshold() {
+        let counter = press(&[
+            //单独一次删除不算连续删除
+            0x41,
+            VK_BACKSPACE,
+            //第2次删除时算作一次连续删除, 包含前面的一次
+            0x42,
+            VK_BACKSPACE,
+            VK_BACKSPACE,
+            //修饰键不打断连续删除
+            VK_LCONTROL,
+            VK_DELETE,
+            0x43,
+            VK_DELETE,
+            VK_DELETE,
+        ]);
+        let corrections = &counter.corrections;
+        assert_eq!(corrections.bursts, 2);
+        assert_eq!(corrections.burst_keys, 3 + 2);
+        assert_eq!(corrections.longest_burst, 3);
+        assert_eq!(corrections.average_burst(), 2.5);
+        assert_eq!(counter.today.corrections, counter.corrections);
+    }
+
+    #[test]
+    fn ratio_of_keystrokes() {
+        //分母为所有按键, 包括修饰键
+        let counter = press(&[0x41, VK_BACKSPACE, VK_LCONTROL, VK_DELETE]);
+        assert_eq!(correction_ratio(&counter.maps), 0.5);
+        assert_eq!(correction_ratio(&press(&[]).maps), 0.);
+        assert_eq!(correction_ratio(&press(&[0x41, 0x42]).maps), 0.);
+    }
+}
@@ -1,43 +1,47 @@
-use chrono::{Local, Utc};
+use chrono::{DateTime, FixedOffset};
 use font_kit::font::Font;
 use num_enum::TryFromPrimitive;
 use raqote::{DrawOptions, DrawTarget, PathBuilder, Point as PointF, Source, StrokeStyle};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, mem, sync::Arc};
 
 use crate::{
+    chord::{is_modifier, Chord, Modifiers},
+    clock::{Clock, SystemClock},
+    config::Config,
+    correction::{draw_trend, CorrectionStats},
+    heatmap::{draw_click_heatmap, draw_keyboard_heatmap, ClickButton, ClickMap, KeyboardLayout},
+    history::History,
+    hold::HoldStats,
+    i18n::{metric_label, set_language, tr},
+    journal::Increments,
+    keys::{parse_key, VK_NUMPAD_ENTER},
+    sequence::Sequence,
+    timeline::Timeline,
     tools::{draw_text, measure_text},
+    typing::{TypingSession, TypingStats},
     window::{HEIGHT, WIDTH},
 };
 
-const VK_CTRL: u32 = 162;
 const VK_ESC: u32 = 27;
 const VK_TAB: u32 = 9;
 const VK_BACKSPACE: u32 = 8;
-// const VK_SHIFT: u32 = 160;
-const VK_ALT: u32 = 164;
 const VK_DELETE: u32 = 46;
 const VK_ENTER: u32 = 13;
-const VK_C: u32 = 67;
-const VK_X: u32 = 88;
-const VK_V: u32 = 86;
-const VK_Z: u32 = 90;
-const VK_Y: u32 = 89;
-const VK_S: u32 = 83;
-
-#[derive(Debug)]
+
+#[derive(Debug, Clone)]
 pub enum KeyEvent {
     KeyPress(u32),
     KeyUp(u32),
 }
 
-#[derive(Serialize, Default, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Default, Deserialize, PartialEq, Debug, Clone)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
 }
 
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[derive(Debug, Eq, PartialEq, TryFromPrimitive, Clone)]
 #[repr(i32)]
 pub enum MouseEvent {
     MouseMove = 0x200,
@@ -46,23 +50,37 @@ pub enum MouseEvent {
     MouseRightButtonDown = 0x204,
     MouseRightButtonUp = 0x205,
     MouseWheelRouting = 0x20A,
-    MouseMiddleButtonDown = 0x2b,
+    MouseMiddleButtonDown = 0x207,
     MouseMiddleButtonUp = 0x20c,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Event {
     KeyEvent(KeyEvent),
     MouseEvent((MouseEvent, Point)),
 }
 
-const MOUSE_LEFT_CLICK_COUNT: &str = "鼠标左击";
-const MOUSE_RIGHT_CLICK_COUNT: &str = "鼠标右击";
-const MOUSE_DOUBLE_CLICKS_COUNT: &str = "鼠标双击";
-const MOUSE_WHEEL_COUNT: &str = "鼠标滚轮";
-const MOUSE_MOVE_COUNT: &str = "鼠标移动";
+const MOUSE_LEFT_CLICK_COUNT: &str = "mouse_left_click";
+const MOUSE_RIGHT_CLICK_COUNT: &str = "mouse_right_click";
+const MOUSE_DOUBLE_CLICKS_COUNT: &str = "mouse_double_click";
+const MOUSE_WHEEL_COUNT: &str = "mouse_wheel";
+const MOUSE_MOVE_COUNT: &str = "mouse_move";
+/// 鼠标移动的像素距离
+const MOUSE_DISTANCE: &str = "mouse_distance";
+/// 两次鼠标事件之间超过这个距离视为跳跃(切换显示器、程序重启等), 不计入距离
+const MOUSE_JUMP_DISTANCE: f64 = 1000.;
+/// 有输入的时间(秒)
+const ACTIVE_TIME: &str = "active_time";
 
-const KEY_KEYSTROKES: &str = "键盘敲击";
+const KEY_KEYSTROKES: &str = "keystrokes";
+/// 不含自动重复的按键次数
+const KEY_PRESSES: &str = "key_presses";
+/// 按住按键时自动重复的次数
+const KEY_REPEATS: &str = "key_repeats";
+/// 按住按键时超过这个时间(毫秒)没有收到重复按下, 视为丢失了松开事件
+const KEY_REPEAT_TIMEOUT: i64 = 1500;
+/// 超过这个时间(毫秒)没有任何输入时, 视为丢失了按下的修饰键的松开事件
+const MODIFIER_TIMEOUT: i64 = 3000;
 const KEY_CTRL_C: &str = "CTRL + C";
 const KEY_CTRL_X: &str = "CTRL + X";
 const KEY_CTRL_V: &str = "CTRL + V";
@@ -70,19 +88,23 @@ const KEY_CTRL_Z: &str = "CTRL + Z";
 const KEY_CTRL_Y: &str = "CTRL + Y";
 const KEY_CTRL_S: &str = "CTRL + S";
 const KEY_ALT_TAB: &str = "ALT + TAB";
-const KEY_BACKSPACE: &str = "Backspace";
-const KEY_ENTER: &str = "Enter";
-const KEY_ESC: &str = "Esc";
-const KEY_DELETE: &str = "Delete";
-const KEY_TAB: &str = "Tab";
+const KEY_BACKSPACE: &str = "backspace";
+const KEY_ENTER: &str = "enter";
+const KEY_ESC: &str = "esc";
+const KEY_DELETE: &str = "delete";
+const KEY_TAB: &str = "tab";
 
-const KEY_LIST: &[&str] = &[
+/// 计数页默认显示的指标
+pub const KEY_LIST: &[&str] = &[
     MOUSE_LEFT_CLICK_COUNT,
     MOUSE_RIGHT_CLICK_COUNT,
     MOUSE_DOUBLE_CLICKS_COUNT,
     MOUSE_WHEEL_COUNT,
     MOUSE_MOVE_COUNT,
+    MOUSE_DISTANCE,
     KEY_KEYSTROKES,
+    KEY_PRESSES,
+    KEY_REPEATS,
     KEY_CTRL_C,
     KEY_CTRL_X,
     KEY_CTRL_V,
@@ -95,8 +117,74 @@ const KEY_LIST: &[&str] = &[
     KEY_ESC,
     KEY_DELETE,
     KEY_TAB,
+    ACTIVE_TIME,
+];
+
+/// 之前版本以显示名称作为计数名称, 读取旧数据时转换为指标ID
+const LEGACY_METRIC_NAMES: &[(&str, &str)] = &[
+    ("鼠标左击", MOUSE_LEFT_CLICK_COUNT),
+    ("鼠标右击", MOUSE_RIGHT_CLICK_COUNT),
+    ("鼠标双击", MOUSE_DOUBLE_CLICKS_COUNT),
+    ("鼠标滚轮", MOUSE_WHEEL_COUNT),
+    ("鼠标移动", MOUSE_MOVE_COUNT),
+    ("键盘敲击", KEY_KEYSTROKES),
+    ("Backspace", KEY_BACKSPACE),
+    ("Enter", KEY_ENTER),
+    ("Esc", KEY_ESC),
+    ("Delete", KEY_DELETE),
+    ("Tab", KEY_TAB),
 ];
 
+/// 旧版本计数名称对应的指标ID
+fn legacy_metric_id(name: &str) -> Option<&'static str> {
+    LEGACY_METRIC_NAMES
+        .iter()
+        .find(|(legacy, _)| *legacy == name)
+        .map(|(_, id)| *id)
+}
+
+/// 将旧版本的计数名称转换为指标ID
+fn migrate_maps(maps: &mut HashMap<String, u128>, timeline: &mut Timeline) {
+    for (legacy, id) in LEGACY_METRIC_NAMES {
+        if let Some(count) = maps.remove(*legacy) {
+            *maps.entry(id.to_string()).or_insert(0) += count;
+        }
+        timeline.rename(legacy, id);
+    }
+}
+
+/// 显示单个按键计数的指标前缀, 如`key:F5`
+const KEY_METRIC_PREFIX: &str = "key:";
+
+/// 纠错率趋势显示的天数
+const TREND_DAYS: usize = 30;
+
+/// 纠错率, 即Backspace和Delete占所有按键的比例
+pub fn correction_ratio(maps: &HashMap<String, u128>) -> f64 {
+    let keystrokes = *maps.get(KEY_KEYSTROKES).unwrap_or(&0);
+    if keystrokes == 0 {
+        return 0.;
+    }
+    let corrections = maps.get(KEY_BACKSPACE).unwrap_or(&0) + maps.get(KEY_DELETE).unwrap_or(&0);
+    corrections as f64 / keystrokes as f64
+}
+
+/// 窗口显示的内容
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum View {
+    /// 各项计数
+    #[default]
+    Counts,
+    /// 键盘热力图
+    KeyboardHeatmap,
+    /// 鼠标点击热力图
+    ClickHeatmap,
+    /// 打字速度
+    Typing,
+    /// 纠错率
+    Corrections,
+}
+
 pub struct DrawConfig<'a> {
     pub background: Source<'a>,
     pub border_color: Source<'a>,
@@ -110,67 +198,281 @@ pub struct DrawConfig<'a> {
     pub font_size: f32,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 pub struct Today {
     pub date: String,
     pub maps: HashMap<String, u128>,
+    pub timeline: Timeline,
+    /// 每个虚拟键码的按下次数
+    pub keys: HashMap<u32, u128>,
+    /// 鼠标点击位置
+    pub clicks: ClickMap,
+    pub typing: TypingStats,
+    /// 按键按住时长
+    pub hold: HoldStats,
+    pub corrections: CorrectionStats,
 }
 
 impl Today {
-    pub fn new() -> Self {
+    pub fn new(now: &DateTime<FixedOffset>) -> Self {
         Self {
-            date: format!("{}", Local::now().format("%Y-%m-%d")),
+            date: format!("{}", now.format("%Y-%m-%d")),
             maps: HashMap::new(),
+            timeline: Timeline::default(),
+            keys: HashMap::new(),
+            clicks: ClickMap::default(),
+            typing: TypingStats::default(),
+            hold: HoldStats::default(),
+            corrections: CorrectionStats::default(),
         }
     }
-    pub fn add_count(&mut self, name: &str) {
-        let date = format!("{}", Local::now().format("%Y-%m-%d"));
-        if self.date != date {
-            self.date = date;
-            self.maps.clear();
+
+    /// `now`为当地时间, 日期变化时重新开始计数, 返回已结束那一天的计数
+    pub fn roll_over(&mut self, now: &DateTime<FixedOffset>) -> Option<Today> {
+        if self.date != format!("{}", now.format("%Y-%m-%d")) {
+            Some(mem::replace(self, Today::new(now)))
+        } else {
+            None
         }
+    }
 
+    /// `minutes`为是否同时统计5分钟的时段
+    pub fn add_count(
+        &mut self,
+        name: &str,
+        count: u128,
+        now: &DateTime<FixedOffset>,
+        minutes: bool,
+    ) {
         if let Some(val) = self.maps.get_mut(name) {
-            *val += 1;
+            *val += count;
         } else {
-            self.maps.insert(name.to_string(), 1);
+            self.maps.insert(name.to_string(), count);
         }
+        self.timeline.add_count(name, count, now, minutes);
     }
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Debug)]
+fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Counter {
+    /// 已经包含在数据中的最后一条增量日志记录的时间(毫秒)
     pub timestamp: i64,
     pub maps: HashMap<String, u128>,
     pub today: Today,
-    pub ctrl_press: bool,
-    pub alt_press: bool,
+    pub history: History,
+    /// 所有日期合计的时段计数
+    pub timeline: Timeline,
+    /// 每个虚拟键码的累计按下次数
+    pub keys: HashMap<u32, u128>,
+    /// 累计鼠标点击位置, 屏幕范围也记录在这里
+    pub clicks: ClickMap,
+    /// 累计打字速度
+    pub typing: TypingStats,
+    /// 正在进行的打字
+    pub typing_session: Option<TypingSession>,
+    /// 当前按下的修饰键, 不保存, 避免丢失的松开事件在重启后仍然有效
+    #[serde(skip)]
+    pub modifiers: Modifiers,
+    /// 按住的按键, 值为(按下时间, 最后一次重复按下的时间), 不保存
+    #[serde(skip)]
+    pub pressed_keys: HashMap<u32, (i64, i64)>,
+    /// 累计按键按住时长
+    pub hold: HoldStats,
+    /// 累计连续删除
+    pub corrections: CorrectionStats,
+    /// 当前连续按下Backspace或Delete的次数
+    pub deletion_run: u64,
     pub last_mouse_click_event: (i64, Point),
     pub last_mouse_wheel_time: i64,
     pub last_mouse_move_time: i64,
+    /// 上一次鼠标事件的位置, 读取存储时清空
+    pub last_mouse_point: Option<Point>,
+    /// 鼠标移动距离不足1像素的部分
+    pub mouse_distance: f64,
+    /// 上一次输入的时间戳(毫秒)
+    pub last_active_time: i64,
+    /// 活跃时间不足1秒的部分(毫秒)
+    pub active_time: i64,
     pub show_today: bool,
+    pub view: View,
+    pub keyboard_layout: KeyboardLayout,
+    /// 点击热力图显示的按键, None为全部按键
+    pub click_button: Option<ClickButton>,
+    #[serde(skip, default = "default_clock")]
+    pub clock: Arc<dyn Clock>,
+    #[serde(skip)]
+    pub config: Config,
+    /// 由配置解析出的组合键
+    #[serde(skip)]
+    pub chords: Vec<Chord>,
+    /// 由配置解析出的按键序列
+    #[serde(skip)]
+    pub sequences: Vec<Sequence>,
+    /// 还没有写入增量日志的计数增量
+    #[serde(skip)]
+    pub increments: Increments,
+    /// 每次收到事件或修改数据后增加, 用于判断是否需要重新绘制
+    #[serde(skip)]
+    pub revision: u64,
 }
 
 impl Counter {
-    /// 清空数据
+    pub fn new() -> Self {
+        Self::with_clock(default_clock())
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        let mut counter = Self {
+            timestamp: 0,
+            maps: HashMap::new(),
+            today: Today::new(&now),
+            history: History::default(),
+            timeline: Timeline::default(),
+            keys: HashMap::new(),
+            clicks: ClickMap::default(),
+            typing: TypingStats::default(),
+            typing_session: None,
+            modifiers: Modifiers::default(),
+            pressed_keys: HashMap::new(),
+            hold: HoldStats::default(),
+            corrections: CorrectionStats::default(),
+            deletion_run: 0,
+            last_mouse_click_event: (0, Point::default()),
+            last_mouse_wheel_time: 0,
+            last_mouse_move_time: 0,
+            last_mouse_point: None,
+            mouse_distance: 0.,
+            last_active_time: 0,
+            active_time: 0,
+            show_today: false,
+            view: View::default(),
+            keyboard_layout: KeyboardLayout::default(),
+            click_button: None,
+            clock,
+            config: Config::default(),
+            chords: vec![],
+            sequences: vec![],
+            increments: Increments::default(),
+            revision: 0,
+        };
+        counter.set_config(Config::default());
+        counter
+    }
+
+    /// 应用配置, 无效的组合键会被忽略
+    pub fn set_config(&mut self, mut config: Config) {
+        set_language(config.language);
+        for metric in &mut config.metrics {
+            if let Some(id) = legacy_metric_id(metric) {
+                *metric = id.to_string();
+            }
+        }
+        self.chords = config
+            .chords
+            .iter()
+            .filter_map(|(name, keys)| {
+                Chord::parse(name, keys)
+                    .map_err(|err| eprintln!("组合键{name}无效: {err}"))
+                    .ok()
+            })
+            .collect();
+        self.sequences = config
+            .sequences
+            .iter()
+            .filter_map(|(name, steps)| {
+                Sequence::parse(name, steps, config.sequence_timeout)
+                    .map_err(|err| eprintln!("按键序列{name}无效: {err}"))
+                    .ok()
+            })
+            .collect();
+        self.config = config;
+        self.revision += 1;
+    }
+
+    /// 将旧版本数据中的计数名称转换为指标ID
+    pub fn migrate_metric_names(&mut self) {
+        migrate_maps(&mut self.maps, &mut self.timeline);
+        migrate_maps(&mut self.today.maps, &mut self.today.timeline);
+        for day in self.history.days.values_mut() {
+            migrate_maps(&mut day.maps, &mut day.timeline);
+        }
+    }
+
+    /// 清空数据, 包括今日计数、历史记录以及各项统计, 只保留显示设置
     pub fn clear(&mut self) {
+        let now = self.clock.now();
         self.maps.clear();
+        self.today = Today::new(&now);
+        self.history = History::default();
+        self.timeline = Timeline::default();
+        self.keys.clear();
+        self.clicks = ClickMap::default();
+        self.typing = TypingStats::default();
+        self.typing_session = None;
+        self.hold = HoldStats::default();
+        self.corrections = CorrectionStats::default();
+        self.deletion_run = 0;
+        self.mouse_distance = 0.;
+        self.active_time = 0;
+        self.increments = Increments::default();
+        self.revision += 1;
     }
+
     pub fn recv(&mut self, event: Event) {
+        self.revision += 1;
+        let now = self.clock.now().timestamp_millis();
+        self.expire_modifiers(now);
+        self.add_active_time(now);
+        if let Event::MouseEvent((_, pt)) = &event {
+            self.clicks.extend(pt);
+            self.add_distance(pt);
+        }
         match event {
             Event::KeyEvent(KeyEvent::KeyPress(code)) => {
                 self.add_count(KEY_KEYSTROKES);
+                self.add_key(code);
+                let repeat = self.press_key(code, now);
+                if repeat {
+                    self.add_count(KEY_REPEATS);
+                } else {
+                    self.add_count(KEY_PRESSES);
+                    self.add_typing(code, now);
+                }
                 // println!("code={code}");
-                if code == VK_CTRL {
-                    self.ctrl_press = true;
+                self.modifiers.press(code);
+                let mut matched: Vec<String> = self
+                    .chords
+                    .iter()
+                    .filter(|chord| chord.matches(&self.modifiers, code))
+                    .map(|chord| chord.name.clone())
+                    .collect();
+                if !repeat {
+                    for sequence in &mut self.sequences {
+                        if sequence.press(&self.modifiers, code, now) {
+                            matched.push(sequence.name.clone());
+                        }
+                    }
                 }
-                if code == VK_ALT {
-                    self.alt_press = true;
+                for name in matched {
+                    self.add_count(&name);
+                }
+                if code == VK_BACKSPACE || code == VK_DELETE {
+                    self.deletion_run += 1;
+                    self.roll_over();
+                    self.corrections.add(self.deletion_run);
+                    self.today.corrections.add(self.deletion_run);
+                } else if !is_modifier(code) {
+                    self.deletion_run = 0;
                 }
                 if code == VK_BACKSPACE {
                     self.add_count(KEY_BACKSPACE);
                 }
-                if code == VK_ENTER {
+                if code == VK_ENTER || code == VK_NUMPAD_ENTER {
                     self.add_count(KEY_ENTER);
                 }
                 if code == VK_DELETE {
@@ -181,73 +483,330 @@ impl Counter {
                 }
                 if code == VK_TAB {
                     self.add_count(KEY_TAB);
-                    if self.alt_press {
-                        self.add_count(KEY_ALT_TAB);
-                    }
                 }
-
-                if self.ctrl_press {
-                    match code {
-                        VK_C => self.add_count(KEY_CTRL_C),
-                        VK_S => self.add_count(KEY_CTRL_S),
-                        VK_X => self.add_count(KEY_CTRL_X),
-                        VK_Z => self.add_count(KEY_CTRL_Z),
-                        VK_Y => self.add_count(KEY_CTRL_Y),
-                        VK_V => self.add_count(KEY_CTRL_V),
-                        _ => (),
-                    }
-                };
             }
             Event::KeyEvent(KeyEvent::KeyUp(code)) => {
-                if code == VK_CTRL {
-                    self.ctrl_press = false;
-                } else if code == VK_ALT {
-                    self.alt_press = false;
+                self.modifiers.release(code);
+                if let Some((down_time, _)) = self.pressed_keys.remove(&code) {
+                    self.roll_over();
+                    self.hold.add(now - down_time);
+                    self.today.hold.add(now - down_time);
                 }
             }
             Event::MouseEvent((MouseEvent::MouseLeftBUttonDown, pt)) => {
                 self.add_count(MOUSE_LEFT_CLICK_COUNT);
-                let now = Utc::now().timestamp_millis();
                 //判断双击
                 let (last_time, last_pt) = &self.last_mouse_click_event;
-                if now - *last_time < 500
-                    && (last_pt.x - pt.x).abs() < 4
-                    && (last_pt.y - pt.y).abs() < 4
+                let distance = self.config.double_click_distance;
+                if now - *last_time < self.config.double_click_time
+                    && (last_pt.x - pt.x).abs() < distance
+                    && (last_pt.y - pt.y).abs() < distance
                 {
                     self.add_count(MOUSE_DOUBLE_CLICKS_COUNT);
                 }
+                self.add_click(ClickButton::Left, &pt);
                 self.last_mouse_click_event = (now, pt);
             }
-            Event::MouseEvent((MouseEvent::MouseRightButtonDown, _)) => {
+            Event::MouseEvent((MouseEvent::MouseRightButtonDown, pt)) => {
                 self.add_count(MOUSE_RIGHT_CLICK_COUNT);
+                self.add_click(ClickButton::Right, &pt);
             }
-            Event::MouseEvent((MouseEvent::MouseWheelRouting, _)) => {
-                let now = Utc::now().timestamp_millis();
-                if now - self.last_mouse_wheel_time > 800 {
-                    self.last_mouse_wheel_time = now;
-                    self.add_count(MOUSE_WHEEL_COUNT);
-                }
+            Event::MouseEvent((MouseEvent::MouseMiddleButtonDown, pt)) => {
+                self.add_click(ClickButton::Middle, &pt);
             }
-            Event::MouseEvent((MouseEvent::MouseMove, _)) => {
-                let now = Utc::now().timestamp_millis();
-                if now - self.last_mouse_move_time > 800 {
-                    self.last_mouse_move_time = now;
-                    self.add_count(MOUSE_MOVE_COUNT);
-                }
+            Event::MouseEvent((MouseEvent::MouseWheelRouting, _))
+                if now - self.last_mouse_wheel_time > self.config.wheel_interval =>
+            {
+                self.last_mouse_wheel_time = now;
+                self.add_count(MOUSE_WHEEL_COUNT);
+            }
+            Event::MouseEvent((MouseEvent::MouseMove, _))
+                if now - self.last_mouse_move_time > self.config.move_interval =>
+            {
+                self.last_mouse_move_time = now;
+                self.add_count(MOUSE_MOVE_COUNT);
             }
             _ => (),
         }
     }
 
     pub fn add_count(&mut self, name: &str) {
+        self.add_count_by(name, 1);
+    }
+
+    pub fn add_count_by(&mut self, name: &str, count: u128) {
         if let Some(val) = self.maps.get_mut(name) {
-            *val += 1;
+            *val += count;
         } else {
-            self.maps.insert(name.to_string(), 1);
+            self.maps.insert(name.to_string(), count);
+        }
+        let now = self.roll_over();
+        let minutes = self.config.five_minute_buckets;
+        self.today.add_count(name, count, &now, minutes);
+        self.timeline.add_count(name, count, &now, minutes);
+        self.increments.add_count(name, count, &now);
+    }
+
+    /// 累计与上一次鼠标位置之间的直线距离, 满1像素才计数
+    fn add_distance(&mut self, point: &Point) {
+        if let Some(last) = self.last_mouse_point.replace(point.clone()) {
+            let distance = ((point.x - last.x) as f64).hypot((point.y - last.y) as f64);
+            if distance > MOUSE_JUMP_DISTANCE {
+                return;
+            }
+            self.mouse_distance += distance;
+            let pixels = self.mouse_distance.floor();
+            if pixels >= 1. {
+                self.mouse_distance -= pixels;
+                self.add_count_by(MOUSE_DISTANCE, pixels as u128);
+            }
+        }
+    }
+
+    /// 两次输入间隔不超过空闲时间时, 间隔计入活跃时间, 满1秒才计数
+    fn add_active_time(&mut self, now: i64) {
+        let gap = now - self.last_active_time;
+        self.last_active_time = now;
+        if gap <= 0 || gap > self.config.idle_time * 1000 {
+            return;
+        }
+        self.active_time += gap;
+        let seconds = self.active_time / 1000;
+        if seconds > 0 {
+            self.active_time -= seconds * 1000;
+            self.add_count_by(ACTIVE_TIME, seconds as u128);
+        }
+    }
+
+    /// 显示鼠标移动距离, 设置了鼠标DPI时换算为米
+    fn format_distance(&self, pixels: u128) -> String {
+        match self.config.mouse_dpi {
+            Some(dpi) if dpi > 0. => {
+                let meters = pixels as f64 / dpi * 0.0254;
+                if meters >= 1000. {
+                    format!("{:.2}km", meters / 1000.)
+                } else {
+                    format!("{meters:.1}m")
+                }
+            }
+            _ => format!("{pixels}px"),
+        }
+    }
+
+    /// 记录按键直方图
+    pub fn add_key(&mut self, code: u32) {
+        self.add_key_by(code, 1);
+    }
+
+    pub fn add_key_by(&mut self, code: u32, count: u128) {
+        let now = self.roll_over();
+        *self.keys.entry(code).or_insert(0) += count;
+        *self.today.keys.entry(code).or_insert(0) += count;
+        self.increments.add_key(code, count, &now);
+    }
+
+    /// 日期变化时将今日计数归档, 返回当前时间
+    fn roll_over(&mut self) -> DateTime<FixedOffset> {
+        let now = self.clock.now();
+        if let Some(finished) = self.today.roll_over(&now) {
+            self.history.archive(finished);
+        }
+        now
+    }
+
+    /// 记录按下时间, 返回是否为按住按键时的自动重复
+    fn press_key(&mut self, code: u32, now: i64) -> bool {
+        match self.pressed_keys.get_mut(&code) {
+            //太久没有收到重复按下, 说明丢失了松开事件, 视为重新按下
+            Some((_, last_time)) if now - *last_time <= KEY_REPEAT_TIMEOUT => {
+                *last_time = now;
+                true
+            }
+            _ => {
+                self.pressed_keys.insert(code, (now, now));
+                false
+            }
+        }
+    }
+
+    /// 一直按住的修饰键会自动重复, 与其他按键组合时也有输入, 长时间没有任何输入说明丢失了松开事件
+    /// (如Win+L锁屏时收不到Win的松开事件), 此时视为修饰键都已松开
+    fn expire_modifiers(&mut self, now: i64) {
+        if now - self.last_active_time > MODIFIER_TIMEOUT {
+            self.modifiers = Modifiers::default();
+            self.pressed_keys.retain(|code, _| !is_modifier(*code));
         }
-        self.today.add_count(name);
     }
 
+    /// 记录打字速度, 停止打字后重新开始计时
+    fn add_typing(&mut self, code: u32, now: i64) {
+        let (words, duration, session) = match self.typing_session.take() {
+            Some(mut session) if session.is_active(now, self.config.typing_idle_time) => {
+                let (words, duration) = session.press(code, now);
+                (words, duration, session)
+            }
+            _ => {
+                self.typing_session = Some(TypingSession::new(code, now));
+                return;
+            }
+        };
+        self.roll_over();
+        self.typing.add(words, duration, &session);
+        self.today.typing.add(words, duration, &session);
+        self.typing_session = Some(session);
+    }
+
+    /// 记录点击位置
+    pub fn add_click(&mut self, button: ClickButton, point: &Point) {
+        self.roll_over();
+        self.clicks.add_click(button, point);
+        self.today.clicks.add_click(button, point);
+    }
+
+    /// 计数或显示设置变化后需要重新绘制
+    pub fn draw_state(&self) -> (u64, View, bool, KeyboardLayout, Option<ClickButton>) {
+        (
+            self.revision,
+            self.view,
+            self.show_today,
+            self.keyboard_layout,
+            self.click_button,
+        )
+    }
+
+    /// 复制绘制当前视图需要的数据
+    pub fn draw_snapshot(&self) -> DrawSnapshot {
+        self.view_snapshot(self.view)
+    }
+
+    /// 只复制绘制`view`需要的数据, 绘制时不需要锁定计数器
+    pub fn view_snapshot(&self, view: View) -> DrawSnapshot {
+        let content = match view {
+            View::Counts => ViewContent::Counts(self.count_items(), self.config.columns),
+            View::Typing => {
+                let stats = if self.show_today {
+                    &self.today.typing
+                } else {
+                    &self.typing
+                };
+                let now = self.clock.now().timestamp_millis();
+                let (kpm, wpm) = match &self.typing_session {
+                    Some(session) if session.is_active(now, self.config.typing_idle_time) => {
+                        (session.kpm(), session.wpm())
+                    }
+                    _ => (0., 0.),
+                };
+                ViewContent::Typing(stats.clone(), kpm, wpm)
+            }
+            View::Corrections => {
+                let (maps, stats) = if self.show_today {
+                    (&self.today.maps, &self.today.corrections)
+                } else {
+                    (&self.maps, &self.corrections)
+                };
+                ViewContent::Corrections(
+                    correction_ratio(maps),
+                    stats.clone(),
+                    self.correction_trend(TREND_DAYS),
+                )
+            }
+            View::KeyboardHeatmap => {
+                let keys = if self.show_today {
+                    &self.today.keys
+                } else {
+                    &self.keys
+                };
+                ViewContent::KeyboardHeatmap(keys.clone(), self.keyboard_layout)
+            }
+            View::ClickHeatmap => {
+                let clicks = if self.show_today {
+                    &self.today.clicks
+                } else {
+                    &self.clicks
+                };
+                ViewContent::ClickHeatmap(clicks.clone(), self.clicks.bounds, self.click_button)
+            }
+        };
+        DrawSnapshot {
+            show_today: self.show_today,
+            content,
+        }
+    }
+
+    /// 各项计数的名称和显示文字
+    fn count_items(&self) -> Vec<(String, String)> {
+        self.config
+            .metrics
+            .iter()
+            .map(|name| {
+                let val = self.metric_value(name);
+                let text = match name.as_str() {
+                    MOUSE_DISTANCE => self.format_distance(val),
+                    ACTIVE_TIME => format_duration(val as i64 * 1000),
+                    _ => format!("{val}"),
+                };
+                let label = match name.strip_prefix(KEY_METRIC_PREFIX) {
+                    Some(key) => key,
+                    None => metric_label(name),
+                };
+                (label.to_string(), text)
+            })
+            .collect()
+    }
+
+    /// 指标的累计或今日计数, `key:`开头的为单个按键的按下次数
+    fn metric_value(&self, name: &str) -> u128 {
+        let (maps, keys) = if self.show_today {
+            (&self.today.maps, &self.today.keys)
+        } else {
+            (&self.maps, &self.keys)
+        };
+        match name.strip_prefix(KEY_METRIC_PREFIX) {
+            Some(key) => parse_key(key)
+                .and_then(|code| keys.get(&code))
+                .copied()
+                .unwrap_or(0),
+            None => *maps.get(name).unwrap_or(&0),
+        }
+    }
+
+    /// 最近`days`天(包含今天)每天的纠错率
+    pub fn correction_trend(&self, days: usize) -> Vec<(String, f64)> {
+        let mut trend: Vec<(String, f64)> = self
+            .history
+            .days
+            .iter()
+            .filter(|(date, _)| **date != self.today.date)
+            .rev()
+            .take(days.saturating_sub(1))
+            .map(|(date, day)| (date.clone(), correction_ratio(&day.maps)))
+            .collect();
+        trend.reverse();
+        trend.push((self.today.date.clone(), correction_ratio(&self.today.maps)));
+        trend
+    }
+}
+
+/// 各视图绘制需要的数据
+enum ViewContent {
+    /// 各项计数的(名称, 显示文字)和每行的数量
+    Counts(Vec<(String, String)>, usize),
+    /// 打字统计, 以及正在进行的打字的KPM和WPM
+    Typing(TypingStats, f64, f64),
+    /// 纠错率、连续删除和每天纠错率的趋势
+    Corrections(f64, CorrectionStats, Vec<(String, f64)>),
+    KeyboardHeatmap(HashMap<u32, u128>, KeyboardLayout),
+    /// 点击位置、屏幕范围和显示的按键
+    ClickHeatmap(ClickMap, Option<(i32, i32, i32, i32)>, Option<ClickButton>),
+}
+
+/// 锁定计数器时复制的绘制数据, 只包含一个视图需要的部分
+pub struct DrawSnapshot {
+    show_today: bool,
+    content: ViewContent,
+}
+
+impl DrawSnapshot {
     pub fn draw(&self, dt: &mut DrawTarget, font: &Font, draw_config: &DrawConfig) {
         // 清空
         dt.fill_rect(
@@ -260,39 +819,82 @@ impl Counter {
         );
         let tab_height = HEIGHT as f32 / 4.;
         let box_margin = 10.;
-        let box_width = (WIDTH as f32 - box_margin) / 6. - box_margin;
-        let box_height = (HEIGHT as f32 - tab_height - box_margin * 4.) / 3.;
-        let corner = 6.;
-        let start_x = 0.;
-        let starty_y = 0.;
-
-        let mut cursor_x = start_x;
-        let mut cursor_y = starty_y + box_margin;
-        for (index, key) in KEY_LIST.iter().enumerate() {
-            let val = if self.show_today {
-                self.today.maps.get(*key).unwrap_or(&0)
-            } else {
-                self.maps.get(*key).unwrap_or(&0)
-            };
-
-            if index > 0 && index % 6 == 0 {
-                cursor_y += box_height + box_margin;
-                cursor_x = start_x;
+
+        match &self.content {
+            ViewContent::Counts(items, columns) => {
+                let items: Vec<(&str, String)> = items
+                    .iter()
+                    .map(|(label, text)| (label.as_str(), text.clone()))
+                    .collect();
+                let height = HEIGHT as f32 - tab_height;
+                let columns = match columns {
+                    0 => auto_columns(items.len(), WIDTH as f32, height),
+                    columns => *columns,
+                };
+                draw_grid(dt, font, draw_config, &items, columns, height, box_margin);
+            }
+            ViewContent::Typing(stats, kpm, wpm) => {
+                let items = [
+                    (tr("typing.current_kpm"), format!("{kpm:.0}")),
+                    (tr("typing.average_kpm"), format!("{:.0}", stats.kpm())),
+                    (tr("typing.peak_kpm"), format!("{:.0}", stats.peak_kpm)),
+                    (tr("typing.duration"), format_duration(stats.duration)),
+                    (tr("typing.current_wpm"), format!("{wpm:.0}")),
+                    (tr("typing.average_wpm"), format!("{:.0}", stats.wpm())),
+                    (tr("typing.peak_wpm"), format!("{:.0}", stats.peak_wpm)),
+                    (tr("typing.words"), format!("{}", stats.words)),
+                ];
+                draw_grid(
+                    dt,
+                    font,
+                    draw_config,
+                    &items,
+                    4,
+                    HEIGHT as f32 - tab_height,
+                    box_margin,
+                );
             }
-            cursor_x += box_margin;
-            draw_box(
-                cursor_x,
-                cursor_y,
-                box_width,
-                box_height,
-                corner,
-                &key,
-                &format!("{val}"),
+            ViewContent::Corrections(ratio, stats, trend) => {
+                //下方为最近每天纠错率的趋势
+                let items = [
+                    (tr("correction.ratio"), format!("{:.1}%", ratio * 100.)),
+                    (tr("correction.bursts"), format!("{}", stats.bursts)),
+                    (
+                        tr("correction.average"),
+                        format!("{:.1}", stats.average_burst()),
+                    ),
+                    (tr("correction.longest"), format!("{}", stats.longest_burst)),
+                ];
+                let grid_height = (HEIGHT as f32 - tab_height) * 0.4;
+                draw_grid(dt, font, draw_config, &items, 4, grid_height, box_margin);
+                draw_trend(
+                    dt,
+                    font,
+                    draw_config,
+                    trend,
+                    box_margin,
+                    grid_height,
+                    WIDTH as f32 - box_margin * 2.,
+                    HEIGHT as f32 - tab_height - grid_height - box_margin,
+                );
+            }
+            ViewContent::KeyboardHeatmap(..) => self.draw_keyboard_heatmap(
                 dt,
                 font,
                 draw_config,
-            );
-            cursor_x += box_width;
+                box_margin,
+                box_margin,
+                WIDTH as f32 - box_margin * 2.,
+                HEIGHT as f32 - tab_height - box_margin * 2.,
+            ),
+            ViewContent::ClickHeatmap(..) => self.draw_click_heatmap(
+                dt,
+                draw_config,
+                box_margin,
+                box_margin,
+                WIDTH as f32 - box_margin * 2.,
+                HEIGHT as f32 - tab_height - box_margin * 2.,
+            ),
         }
 
         // 绘制底部文字
@@ -306,8 +908,8 @@ impl Counter {
 
         let text2_suffix = if self.show_today { "↑" } else { "→" };
 
-        let text1 = format!("{}累计输入", text1_suffix);
-        let text2 = format!("今日输入{}", text2_suffix);
+        let text1 = format!("{}{}", text1_suffix, tr("button.total"));
+        let text2 = format!("{}{}", tr("button.today"), text2_suffix);
 
         draw_button(
             button_left,
@@ -334,6 +936,121 @@ impl Counter {
             draw_config,
         );
     }
+
+    /// 绘制键盘热力图, 快照不是键盘热力图视图时不绘制
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_keyboard_heatmap(
+        &self,
+        dt: &mut DrawTarget,
+        font: &Font,
+        draw_config: &DrawConfig,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) {
+        if let ViewContent::KeyboardHeatmap(keys, layout) = &self.content {
+            draw_keyboard_heatmap(dt, font, draw_config, keys, *layout, x, y, width, height);
+        }
+    }
+
+    /// 点击热力图的屏幕范围
+    pub fn click_bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        match &self.content {
+            ViewContent::ClickHeatmap(_, bounds, _) => *bounds,
+            _ => None,
+        }
+    }
+
+    /// 绘制点击热力图, 快照不是点击热力图视图时不绘制
+    pub fn draw_click_heatmap(
+        &self,
+        dt: &mut DrawTarget,
+        draw_config: &DrawConfig,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    ) {
+        if let ViewContent::ClickHeatmap(clicks, bounds, button) = &self.content {
+            draw_click_heatmap(
+                dt,
+                draw_config,
+                clicks,
+                *bounds,
+                *button,
+                x,
+                y,
+                width,
+                height,
+            );
+        }
+    }
+}
+
+/// 时长显示为小时和分钟, `duration`为毫秒
+fn format_duration(duration: i64) -> String {
+    let minutes = duration / 1000 / 60;
+    if minutes >= 60 {
+        format!(
+            "{}{}{}{}",
+            minutes / 60,
+            tr("unit.hour"),
+            minutes % 60,
+            tr("unit.minute")
+        )
+    } else if minutes > 0 {
+        format!("{minutes}{}", tr("unit.minute"))
+    } else {
+        format!("{}{}", duration / 1000, tr("unit.second"))
+    }
+}
+
+/// 自动计算每行的数量, 使计数框接近正方形
+fn auto_columns(count: usize, width: f32, height: f32) -> usize {
+    let columns = (count as f32 * width / height).sqrt().ceil() as usize;
+    columns.clamp(1, count.max(1))
+}
+
+/// 在窗口顶部高为`height`的区域按网格绘制多个计数框, 每行`columns`个, 行数随数量增加
+fn draw_grid(
+    dt: &mut DrawTarget,
+    font: &Font,
+    draw_config: &DrawConfig,
+    items: &[(&str, String)],
+    columns: usize,
+    height: f32,
+    box_margin: f32,
+) {
+    let box_width = (WIDTH as f32 - box_margin) / columns as f32 - box_margin;
+    let rows = items.len().div_ceil(columns);
+    let box_height = (height - box_margin * (rows + 1) as f32) / rows as f32;
+    let corner = 6.;
+    let start_x = 0.;
+    let starty_y = 0.;
+
+    let mut cursor_x = start_x;
+    let mut cursor_y = starty_y + box_margin;
+    for (index, (label, text)) in items.iter().enumerate() {
+        if index > 0 && index % columns == 0 {
+            cursor_y += box_height + box_margin;
+            cursor_x = start_x;
+        }
+        cursor_x += box_margin;
+        draw_box(
+            cursor_x,
+            cursor_y,
+            box_width,
+            box_height,
+            corner,
+            label,
+            text,
+            dt,
+            font,
+            draw_config,
+        );
+        cursor_x += box_width;
+    }
 }
 
 fn draw_box(
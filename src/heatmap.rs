@@ -0,0 +1,477 @@
+use font_kit::font::Font;
+use raqote::{DrawTarget, PathBuilder, Point as PointF, SolidSource, Source};
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, collections::HashMap};
+
+use crate::{
+    counter::{DrawConfig, Point},
+    keys::{key_name, VK_NUMPAD_ENTER},
+    tools::{draw_text, measure_text},
+};
+
+/// 键盘布局
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, Default)]
+pub enum KeyboardLayout {
+    /// 104键美式布局
+    #[default]
+    Ansi,
+    /// 105键欧式布局
+    Iso,
+    /// 87键无数字小键盘布局
+    Tkl,
+}
+
+/// 键帽位置, 单位为一个标准按键的宽度
+struct KeyCap {
+    code: u32,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+const F_ROW: &[(u32, f32)] = &[
+    (0x1B, 1.),
+    (0, 1.),
+    (0x70, 1.),
+    (0x71, 1.),
+    (0x72, 1.),
+    (0x73, 1.),
+    (0, 0.5),
+    (0x74, 1.),
+    (0x75, 1.),
+    (0x76, 1.),
+    (0x77, 1.),
+    (0, 0.5),
+    (0x78, 1.),
+    (0x79, 1.),
+    (0x7A, 1.),
+    (0x7B, 1.),
+];
+
+const NUMBER_ROW: &[(u32, f32)] = &[
+    (0xC0, 1.),
+    (0x31, 1.),
+    (0x32, 1.),
+    (0x33, 1.),
+    (0x34, 1.),
+    (0x35, 1.),
+    (0x36, 1.),
+    (0x37, 1.),
+    (0x38, 1.),
+    (0x39, 1.),
+    (0x30, 1.),
+    (0xBD, 1.),
+    (0xBB, 1.),
+    (0x08, 2.),
+];
+
+const Q_ROW: &[(u32, f32)] = &[
+    (0x09, 1.5),
+    (0x51, 1.),
+    (0x57, 1.),
+    (0x45, 1.),
+    (0x52, 1.),
+    (0x54, 1.),
+    (0x59, 1.),
+    (0x55, 1.),
+    (0x49, 1.),
+    (0x4F, 1.),
+    (0x50, 1.),
+    (0xDB, 1.),
+    (0xDD, 1.),
+];
+
+const A_ROW: &[(u32, f32)] = &[
+    (0x14, 1.75),
+    (0x41, 1.),
+    (0x53, 1.),
+    (0x44, 1.),
+    (0x46, 1.),
+    (0x47, 1.),
+    (0x48, 1.),
+    (0x4A, 1.),
+    (0x4B, 1.),
+    (0x4C, 1.),
+    (0xBA, 1.),
+    (0xDE, 1.),
+];
+
+const Z_ROW: &[(u32, f32)] = &[
+    (0x5A, 1.),
+    (0x58, 1.),
+    (0x43, 1.),
+    (0x56, 1.),
+    (0x42, 1.),
+    (0x4E, 1.),
+    (0x4D, 1.),
+    (0xBC, 1.),
+    (0xBE, 1.),
+    (0xBF, 1.),
+    (0xA1, 2.75),
+];
+
+const SPACE_ROW: &[(u32, f32)] = &[
+    (0xA2, 1.25),
+    (0x5B, 1.25),
+    (0xA4, 1.25),
+    (0x20, 6.25),
+    (0xA5, 1.25),
+    (0x5C, 1.25),
+    (0x5D, 1.25),
+    (0xA3, 1.25),
+];
+
+/// 方向键和编辑键区, 每行一组
+const NAVIGATION: &[&[(u32, f32)]] = &[
+    &[(0x2C, 1.), (0x91, 1.), (0x13, 1.)],
+    &[(0x2D, 1.), (0x24, 1.), (0x21, 1.)],
+    &[(0x2E, 1.), (0x23, 1.), (0x22, 1.)],
+    &[],
+    &[(0, 1.), (0x26, 1.)],
+    &[(0x25, 1.), (0x28, 1.), (0x27, 1.)],
+];
+
+/// 数字小键盘, +和Enter占两行单独处理
+const NUMPAD: &[&[(u32, f32)]] = &[
+    &[],
+    &[(0x90, 1.), (0x6F, 1.), (0x6A, 1.), (0x6D, 1.)],
+    &[(0x67, 1.), (0x68, 1.), (0x69, 1.)],
+    &[(0x64, 1.), (0x65, 1.), (0x66, 1.)],
+    &[(0x61, 1.), (0x62, 1.), (0x63, 1.)],
+    &[(0x60, 2.), (0x6E, 1.)],
+];
+
+/// 功能键行与下面的按键之间留出半行空隙
+fn row_y(row: usize) -> f32 {
+    if row == 0 {
+        0.
+    } else {
+        row as f32 + 0.5
+    }
+}
+
+/// 依次排列一行按键, 键码为0表示空隙
+fn push_row(caps: &mut Vec<KeyCap>, x: f32, row: usize, keys: &[(u32, f32)]) -> f32 {
+    let mut x = x;
+    for (code, width) in keys {
+        if *code != 0 {
+            caps.push(KeyCap {
+                code: *code,
+                x,
+                y: row_y(row),
+                width: *width,
+                height: 1.,
+            });
+        }
+        x += width;
+    }
+    x
+}
+
+fn keycaps(layout: KeyboardLayout) -> Vec<KeyCap> {
+    let mut caps = vec![];
+    push_row(&mut caps, 0., 0, F_ROW);
+    push_row(&mut caps, 0., 1, NUMBER_ROW);
+    let x = push_row(&mut caps, 0., 2, Q_ROW);
+    let x3 = push_row(&mut caps, 0., 3, A_ROW);
+    if layout == KeyboardLayout::Iso {
+        //ISO布局的Enter占两行, 原来的\键移到Enter左侧, 左Shift右边多出一个键
+        push_row(&mut caps, x3, 3, &[(0xDC, 1.)]);
+        caps.push(KeyCap {
+            code: 0x0D,
+            x: x + 0.25,
+            y: row_y(2),
+            width: 1.25,
+            height: 2.,
+        });
+        push_row(&mut caps, 0., 4, &[(0xA0, 1.25), (0xE2, 1.)]);
+        push_row(&mut caps, 2.25, 4, Z_ROW);
+    } else {
+        push_row(&mut caps, x, 2, &[(0xDC, 1.5)]);
+        push_row(&mut caps, x3, 3, &[(0x0D, 2.25)]);
+        push_row(&mut caps, 0., 4, &[(0xA0, 2.25)]);
+        push_row(&mut caps, 2.25, 4, Z_ROW);
+    }
+    push_row(&mut caps, 0., 5, SPACE_ROW);
+
+    for (row, keys) in NAVIGATION.iter().enumerate() {
+        push_row(&mut caps, 15.25, row, keys);
+    }
+
+    if layout != KeyboardLayout::Tkl {
+        for (row, keys) in NUMPAD.iter().enumerate() {
+            push_row(&mut caps, 18.5, row, keys);
+        }
+        for (code, row) in [(0x6B, 2), (VK_NUMPAD_ENTER, 4)] {
+            caps.push(KeyCap {
+                code,
+                x: 21.5,
+                y: row_y(row),
+                width: 1.,
+                height: 2.,
+            });
+        }
+    }
+    caps
+}
+
+/// 键帽上显示的简短名称
+fn cap_label(code: u32) -> Cow<'static, str> {
+    let label = match code {
+        0x08 => "Bksp",
+        0x14 => "Caps",
+        0x21 => "PgUp",
+        0x22 => "PgDn",
+        0x25 => "←",
+        0x26 => "↑",
+        0x27 => "→",
+        0x28 => "↓",
+        0x2C => "PrtSc",
+        0x2D => "Ins",
+        0x2E => "Del",
+        0x5B | 0x5C => "Win",
+        0x60..=0x69 => return Cow::Owned(format!("{}", code - 0x60)),
+        0x6A => "*",
+        0x6B => "+",
+        0x6D => "-",
+        0x6E => ".",
+        0x6F => "/",
+        0x90 => "Num",
+        0x91 => "ScrLk",
+        0xA0 | 0xA1 => "Shift",
+        0xA2 | 0xA3 => "Ctrl",
+        0xA4 | 0xA5 => "Alt",
+        0xE2 => "\\",
+        VK_NUMPAD_ENTER => "Enter",
+        _ => return key_name(code),
+    };
+    Cow::Borrowed(label)
+}
+
+/// 热度颜色, `heat`为0到1, 从蓝色经绿色、黄色渐变到红色
+pub fn heat_color(heat: f32) -> SolidSource {
+    const STOPS: [(f32, f32, f32); 4] = [
+        (0x2c as f32, 0x5a as f32, 0xa0 as f32),
+        (0x3c as f32, 0xb3 as f32, 0x71 as f32),
+        (0xf0 as f32, 0xd0 as f32, 0x3c as f32),
+        (0xe0 as f32, 0x3c as f32, 0x31 as f32),
+    ];
+    let position = heat.clamp(0., 1.) * (STOPS.len() - 1) as f32;
+    let index = (position as usize).min(STOPS.len() - 2);
+    let t = position - index as f32;
+    let (from, to) = (STOPS[index], STOPS[index + 1]);
+    SolidSource::from_unpremultiplied_argb(
+        0xFF,
+        (from.0 + (to.0 - from.0) * t) as u8,
+        (from.1 + (to.1 - from.1) * t) as u8,
+        (from.2 + (to.2 - from.2) * t) as u8,
+    )
+}
+
+/// 在指定区域内绘制键盘热力图, 每个键按次数着色(对数刻度)
+#[allow(clippy::too_many_arguments)]
+pub fn draw_keyboard_heatmap(
+    dt: &mut DrawTarget,
+    font: &Font,
+    draw_config: &DrawConfig,
+    keys: &HashMap<u32, u128>,
+    layout: KeyboardLayout,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+) {
+    let caps = keycaps(layout);
+    let columns = caps.iter().map(|cap| cap.x + cap.width).fold(0., f32::max);
+    let rows = caps.iter().map(|cap| cap.y + cap.height).fold(0., f32::max);
+    let unit = (width / columns).min(height / rows);
+    let left = x + (width - unit * columns) / 2.;
+    let top = y + (height - unit * rows) / 2.;
+    let gap = unit * 0.08;
+
+    let max = caps
+        .iter()
+        .map(|cap| *keys.get(&cap.code).unwrap_or(&0))
+        .max()
+        .unwrap_or(0);
+    let point_size = unit * 0.32;
+    for cap in &caps {
+        let count = *keys.get(&cap.code).unwrap_or(&0);
+        let heat = if max > 0 {
+            ((count as f64).ln_1p() / (max as f64).ln_1p()) as f32
+        } else {
+            0.
+        };
+        let cap_x = left + cap.x * unit + gap / 2.;
+        let cap_y = top + cap.y * unit + gap / 2.;
+        let cap_width = cap.width * unit - gap;
+        let cap_height = cap.height * unit - gap;
+        let color = if count > 0 {
+            Source::Solid(heat_color(heat))
+        } else {
+            draw_config.border_color.clone()
+        };
+        dt.fill_rect(
+            cap_x,
+            cap_y,
+            cap_width,
+            cap_height,
+            &color,
+            &draw_config.draw_options,
+        );
+
+        //名称过长时截短
+        let mut label = cap_label(cap.code).to_string();
+        let mut size = measure_text(font, point_size, &label);
+        while size.x > cap_width && label.chars().count() > 1 {
+            label.pop();
+            size = measure_text(font, point_size, &label);
+        }
+        draw_text(
+            dt,
+            font,
+            point_size,
+            &label,
+            PointF::new(
+                cap_x + (cap_width - size.x) / 2.,
+                cap_y + cap_height / 2. + point_size / 3.,
+            ),
+            &draw_config.text_color,
+            &draw_config.draw_options,
+        );
+    }
+}
+
+/// 点击热力图每个格子对应的屏幕像素
+const CLICK_CELL_SIZE: i32 = 16;
+
+/// 鼠标按键
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum ClickButton {
+    Left,
+    Right,
+    Middle,
+}
+
+/// 鼠标点击热力图, 将屏幕按格子统计点击次数
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct ClickMap {
+    /// 每个按键在每个格子的点击次数, 键为格子坐标
+    pub cells: HashMap<ClickButton, HashMap<(i32, i32), u64>>,
+    /// 出现过的鼠标位置范围(左, 上, 右, 下), 多显示器时可能有负坐标
+    pub bounds: Option<(i32, i32, i32, i32)>,
+}
+
+impl ClickMap {
+    /// 扩展屏幕范围, 所有鼠标事件都会调用
+    pub fn extend(&mut self, point: &Point) {
+        self.bounds = Some(match self.bounds {
+            Some((left, top, right, bottom)) => (
+                left.min(point.x),
+                top.min(point.y),
+                right.max(point.x),
+                bottom.max(point.y),
+            ),
+            None => (point.x, point.y, point.x, point.y),
+        });
+    }
+
+    pub fn add_click(&mut self, button: ClickButton, point: &Point) {
+        self.extend(point);
+        let cell = (
+            point.x.div_euclid(CLICK_CELL_SIZE),
+            point.y.div_euclid(CLICK_CELL_SIZE),
+        );
+        *self
+            .cells
+            .entry(button)
+            .or_default()
+            .entry(cell)
+            .or_insert(0) += 1;
+    }
+
+    pub fn merge(&mut self, other: ClickMap) {
+        if let Some((left, top, right, bottom)) = other.bounds {
+            self.extend(&Point { x: left, y: top });
+            self.extend(&Point { x: right, y: bottom });
+        }
+        for (button, cells) in other.cells {
+            let total = self.cells.entry(button).or_default();
+            for (cell, count) in cells {
+                *total.entry(cell).or_insert(0) += count;
+            }
+        }
+    }
+
+    /// 指定按键(None为所有按键)每个格子的点击次数
+    fn counts(&self, button: Option<ClickButton>) -> HashMap<(i32, i32), u64> {
+        let mut counts = HashMap::new();
+        for (_, cells) in self
+            .cells
+            .iter()
+            .filter(|(key, _)| button.is_none_or(|button| **key == button))
+        {
+            for (cell, count) in cells {
+                *counts.entry(*cell).or_insert(0) += count;
+            }
+        }
+        counts
+    }
+}
+
+/// 在指定区域内按屏幕范围`bounds`的比例绘制点击热力图(对数刻度)
+#[allow(clippy::too_many_arguments)]
+pub fn draw_click_heatmap(
+    dt: &mut DrawTarget,
+    draw_config: &DrawConfig,
+    clicks: &ClickMap,
+    bounds: Option<(i32, i32, i32, i32)>,
+    button: Option<ClickButton>,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+) {
+    let Some((left, top, right, bottom)) = bounds else {
+        return;
+    };
+    let (left, top) = (
+        left.div_euclid(CLICK_CELL_SIZE),
+        top.div_euclid(CLICK_CELL_SIZE),
+    );
+    let (right, bottom) = (
+        right.div_euclid(CLICK_CELL_SIZE),
+        bottom.div_euclid(CLICK_CELL_SIZE),
+    );
+    let columns = (right - left + 1) as f32;
+    let rows = (bottom - top + 1) as f32;
+    let cell = (width / columns).min(height / rows);
+    let origin_x = x + (width - cell * columns) / 2.;
+    let origin_y = y + (height - cell * rows) / 2.;
+
+    let mut pb = PathBuilder::new();
+    pb.rect(origin_x, origin_y, cell * columns, cell * rows);
+    dt.stroke(
+        &pb.finish(),
+        &draw_config.border_color,
+        &draw_config.stroke_style,
+        &draw_config.draw_options,
+    );
+
+    let counts = clicks.counts(button);
+    let max = counts.values().copied().max().unwrap_or(0);
+    for ((cell_x, cell_y), count) in counts {
+        let heat = ((count as f64).ln_1p() / (max as f64).ln_1p()) as f32;
+        //点击稀疏时格子太小看不清, 最小绘制为2像素
+        let size = cell.max(2.);
+        dt.fill_rect(
+            origin_x + (cell_x - left) as f32 * cell,
+            origin_y + (cell_y - top) as f32 * cell,
+            size,
+            size,
+            &Source::Solid(heat_color(heat)),
+            &draw_config.draw_options,
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::counter::Today;
+
+/// 历史记录, 保存每一天结束时的计数
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct History {
+    /// 键为`%Y-%m-%d`格式的日期
+    pub days: BTreeMap<String, Today>,
+}
+
+impl History {
+    /// 归档一天的计数, 同一日期已有记录时累加(如时区变化后回到了之前的日期)
+    pub fn archive(&mut self, today: Today) {
+        if let Some(day) = self.days.get_mut(&today.date) {
+            for (name, count) in today.maps {
+                *day.maps.entry(name).or_insert(0) += count;
+            }
+            day.timeline.merge(today.timeline);
+            for (code, count) in today.keys {
+                *day.keys.entry(code).or_insert(0) += count;
+            }
+            day.clicks.merge(today.clicks);
+            day.typing.merge(today.typing);
+            day.hold.merge(today.hold);
+            day.corrections.merge(today.corrections);
+        } else {
+            self.days.insert(today.date.clone(), today);
+        }
+    }
+
+    pub fn day(&self, date: NaiveDate) -> Option<&Today> {
+        self.days.get(&format!("{}", date.format("%Y-%m-%d")))
+    }
+
+    /// 统计`from`到`to`(包含)之间每天计数的合计
+    pub fn range(&self, from: NaiveDate, to: NaiveDate) -> HashMap<String, u128> {
+        let from = format!("{}", from.format("%Y-%m-%d"));
+        let to = format!("{}", to.format("%Y-%m-%d"));
+        let mut maps = HashMap::new();
+        if from > to {
+            return maps;
+        }
+        for day in self.days.range(from..=to).map(|(_, day)| day) {
+            for (name, count) in &day.maps {
+                *maps.entry(name.clone()).or_insert(0) += count;
+            }
+        }
+        maps
+    }
+
+    /// `date`所在周(周一至周日)的合计
+    pub fn week(&self, date: NaiveDate) -> HashMap<String, u128> {
+        let monday = date - Duration::days(date.weekday().num_days_from_monday() as i64);
+        self.range(monday, monday + Duration::days(6))
+    }
+
+    /// `date`所在月的合计
+    pub fn month(&self, date: NaiveDate) -> HashMap<String, u128> {
+        let first = date.with_day(1).unwrap_or(date);
+        let next_month = if first.month() == 12 {
+            NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)
+        } else {
+            NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)
+        };
+        let last = next_month.map_or(first, |next| next - Duration::days(1));
+        self.range(first, last)
+    }
+}
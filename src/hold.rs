@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+/// 按住时长区间的上限(毫秒), 最后一个区间没有上限
+const HOLD_BUCKETS: [i64; 8] = [50, 100, 150, 200, 300, 500, 1000, 2000];
+
+/// 按键按住时长的分布
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct HoldStats {
+    /// 每个区间的次数, 比`HOLD_BUCKETS`多一个区间
+    pub buckets: [u64; HOLD_BUCKETS.len() + 1],
+    pub count: u64,
+    /// 总时长(毫秒)
+    pub duration: i64,
+}
+
+impl HoldStats {
+    pub fn add(&mut self, duration: i64) {
+        let index = HOLD_BUCKETS
+            .iter()
+            .position(|max| duration < *max)
+            .unwrap_or(HOLD_BUCKETS.len());
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.duration += duration;
+    }
+
+    pub fn merge(&mut self, other: HoldStats) {
+        for (total, count) in self.buckets.iter_mut().zip(other.buckets) {
+            *total += count;
+        }
+        self.count += other.count;
+        self.duration += other.duration;
+    }
+
+    /// 平均按住时长(毫秒)
+    pub fn average(&self) -> i64 {
+        if self.count > 0 {
+            self.duration / self.count as i64
+        } else {
+            0
+        }
+    }
+
+    /// 每个区间的名称和次数
+    pub fn buckets(&self) -> Vec<(String, u64)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(index, count)| {
+                let label = match index {
+                    0 => format!("<{}ms", HOLD_BUCKETS[0]),
+                    _ if index == HOLD_BUCKETS.len() => format!(">={}ms", HOLD_BUCKETS[index - 1]),
+                    _ => format!("{}-{}ms", HOLD_BUCKETS[index - 1], HOLD_BUCKETS[index]),
+                };
+                (label, *count)
+            })
+            .collect()
+    }
+}
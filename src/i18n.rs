@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// 界面语言
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+    #[serde(rename = "en")]
+    En,
+}
+
+impl Language {
+    /// 配置文件中的名称
+    pub fn code(&self) -> &'static str {
+        match self {
+            Language::ZhCn => "zh-CN",
+            Language::En => "en",
+        }
+    }
+}
+
+/// 支持的语言和菜单中显示的名称
+pub const LANGUAGES: &[(Language, &str)] = &[(Language::ZhCn, "简体中文"), (Language::En, "English")];
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        1 => Language::En,
+        _ => Language::ZhCn,
+    }
+}
+
+/// 翻译表: (键, 简体中文, English)
+const TRANSLATIONS: &[(&str, &str, &str)] = &[
+    ("app.name", "按键统计", "Keyboard Counter"),
+    ("alert.title", "温馨提示", "Notice"),
+    ("alert.startup_on", "已设置开机启动！", "Run at startup enabled!"),
+    ("alert.startup_off", "已关闭开机启动！", "Run at startup disabled!"),
+    (
+        "alert.follow_system_unsupported",
+        "当前系统不支持跟随系统主题！",
+        "Following the system theme is not supported on this system!",
+    ),
+    (
+        "alert.storage_damaged",
+        "数据文件无法读取, 已保留原文件, 可以使用repair命令修复",
+        "The data file could not be read. It has been kept and can be fixed with the repair command.",
+    ),
+    (
+        "alert.storage_newer",
+        "数据由更高版本的程序保存, 为避免覆盖, 本次运行不会保存数据",
+        "The data was saved by a newer version of the program. It will not be saved in this session to avoid overwriting it.",
+    ),
+    ("alert.menu_failed", "操作失败", "The operation failed"),
+    (
+        "alert.clear_not_saved",
+        "数据已清空, 但无法保存, 重新启动后仍是原来的数据",
+        "The data was cleared but could not be saved. The previous data will be back after a restart.",
+    ),
+    (
+        "alert.window_failed",
+        "窗口出错, 程序将保存数据后退出",
+        "The window failed. The program will save the data and exit.",
+    ),
+    // 错误提示
+    ("error.storage_incomplete", "存储文件不完整", "The data file is incomplete"),
+    (
+        "error.storage_newer",
+        "存储文件版本{version}高于程序支持的版本{supported}, 请升级程序",
+        "The data file version {version} is newer than the supported version {supported}. Please upgrade the program.",
+    ),
+    (
+        "error.no_daily_data_v0",
+        "版本0没有按天保存的数据",
+        "Version 0 has no per-day data",
+    ),
+    (
+        "error.storage_checksum",
+        "存储文件校验和不一致, 数据已损坏",
+        "The data file checksum does not match. The data is damaged.",
+    ),
+    ("error.nothing_to_salvage", "没有可以恢复的计数", "No counts could be salvaged"),
+    ("error.storage_moved", "{error}, 已移动到{path}", "{error}. It was moved to {path}"),
+    (
+        "error.storage_not_moved",
+        "{error}, 无法移动({reason})",
+        "{error}. It could not be moved ({reason})",
+    ),
+    (
+        "error.read_only",
+        "保存的数据无法读取, 为避免覆盖不再保存",
+        "The saved data could not be read. Nothing will be saved to avoid overwriting it.",
+    ),
+    (
+        "error.startup_unsupported",
+        "当前平台不支持开机启动!",
+        "Run at startup is not supported on this platform!",
+    ),
+    ("error.exe_path", "exe路径读取失败!", "Failed to read the executable path!"),
+    ("button.total", "累计输入", "Total"),
+    ("button.today", "今日输入", "Today"),
+    // 指标
+    ("metric.mouse_left_click", "鼠标左击", "Left Click"),
+    ("metric.mouse_right_click", "鼠标右击", "Right Click"),
+    ("metric.mouse_double_click", "鼠标双击", "Double Click"),
+    ("metric.mouse_wheel", "鼠标滚轮", "Wheel"),
+    ("metric.mouse_move", "鼠标移动", "Mouse Move"),
+    ("metric.mouse_distance", "鼠标距离", "Distance"),
+    ("metric.active_time", "活跃时间", "Active Time"),
+    ("metric.keystrokes", "键盘敲击", "Keystrokes"),
+    ("metric.key_presses", "有效敲击", "Presses"),
+    ("metric.key_repeats", "自动重复", "Repeats"),
+    ("metric.backspace", "Backspace", "Backspace"),
+    ("metric.enter", "Enter", "Enter"),
+    ("metric.esc", "Esc", "Esc"),
+    ("metric.delete", "Delete", "Delete"),
+    ("metric.tab", "Tab", "Tab"),
+    // 打字速度
+    ("typing.current_kpm", "当前KPM", "KPM Now"),
+    ("typing.average_kpm", "平均KPM", "Avg KPM"),
+    ("typing.peak_kpm", "最高KPM", "Peak KPM"),
+    ("typing.duration", "打字时长", "Typing Time"),
+    ("typing.current_wpm", "当前WPM", "WPM Now"),
+    ("typing.average_wpm", "平均WPM", "Avg WPM"),
+    ("typing.peak_wpm", "最高WPM", "Peak WPM"),
+    ("typing.words", "单词数", "Words"),
+    // 纠错率
+    ("correction.ratio", "纠错率", "Corrections"),
+    ("correction.bursts", "连续删除", "Bursts"),
+    ("correction.average", "平均删除", "Avg Burst"),
+    ("correction.longest", "最长删除", "Longest"),
+    ("correction.trend", "每日纠错率(最高{max})", "Daily corrections (max {max})"),
+    // 时长单位
+    ("unit.hour", "时", "h"),
+    ("unit.minute", "分", "m"),
+    ("unit.second", "秒", "s"),
+    // 菜单
+    ("menu.settings", "设置", "Settings"),
+    ("menu.startup", "开机启动", "Run at Startup"),
+    ("menu.save_image", "保存图片", "Save Image"),
+    ("menu.clear", "清空数据", "Clear Data"),
+    ("menu.export", "导出数据", "Export Data"),
+    ("menu.save_keyboard_heatmap", "保存键盘热力图", "Save Keyboard Heatmap"),
+    ("menu.save_click_heatmap", "保存点击热力图", "Save Click Heatmap"),
+    ("menu.theme", "主题", "Theme"),
+    ("menu.language", "语言(Language)", "Language"),
+    ("menu.view", "视图", "View"),
+    ("theme.dark", "深色", "Dark"),
+    ("theme.light", "浅色", "Light"),
+    ("theme.high-contrast", "高对比度", "High Contrast"),
+    ("theme.custom", "自定义", "Custom"),
+    ("theme.follow_system", "跟随系统", "Follow System"),
+    ("view.counts", "计数", "Counts"),
+    ("view.keyboard_heatmap", "键盘热力图", "Keyboard Heatmap"),
+    ("view.click_heatmap", "点击热力图", "Click Heatmap"),
+    ("view.typing", "打字速度", "Typing Speed"),
+    ("view.corrections", "纠错率", "Corrections"),
+    ("layout.ansi", "ANSI键盘", "ANSI Keyboard"),
+    ("layout.iso", "ISO键盘", "ISO Keyboard"),
+    ("layout.tkl", "无数字键盘", "TKL Keyboard"),
+    ("clicks.all", "全部点击", "All Clicks"),
+    ("clicks.left", "左键点击", "Left Clicks"),
+    ("clicks.right", "右键点击", "Right Clicks"),
+    ("clicks.middle", "中键点击", "Middle Clicks"),
+    // 保存的文件名
+    ("file.keyboard_heatmap", "键盘热力图", "keyboard-heatmap"),
+    ("file.click_heatmap", "点击热力图", "click-heatmap"),
+    // 命令行输出
+    ("cli.total", "累计", "Total"),
+    ("cli.keys", "按键", "Keys"),
+    ("cli.hold", "按住时长", "Hold time"),
+    ("cli.count", "次数", "Count"),
+    ("cli.average", "平均", "Average"),
+    ("cli.longest", "最长", "Longest"),
+    ("cli.salvaged", "恢复的计数", "Salvaged counters"),
+    ("cli.history_days", "历史记录天数", "History days"),
+    ("cli.repaired", "已写入", "Written to"),
+    ("cli.moved", "原文件已移动到", "Previous file moved to"),
+    (
+        "cli.repair_sqlite",
+        "使用SQLite数据库时不能修复, 请使用没有启用sqlite功能的版本修复存储文件",
+        "Repair is not supported with the SQLite database. Use a build without the sqlite feature to repair the data file.",
+    ),
+];
+
+fn lookup(key: &str) -> Option<&'static str> {
+    TRANSLATIONS
+        .iter()
+        .find(|(name, _, _)| *name == key)
+        .map(|(_, zh_cn, en)| match language() {
+            Language::ZhCn => *zh_cn,
+            Language::En => *en,
+        })
+}
+
+/// 当前语言的文字, 没有翻译时返回键本身
+pub fn tr(key: &'static str) -> &'static str {
+    lookup(key).unwrap_or(key)
+}
+
+/// 当前语言的文字, 并将其中的`{名称}`替换为参数的值
+pub fn tr_args(key: &'static str, args: &[(&str, &str)]) -> String {
+    args.iter().fold(tr(key).to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), value)
+    })
+}
+
+/// 指标的显示名称, 组合键、按键序列等用户定义的指标直接显示名称
+pub fn metric_label(id: &str) -> &str {
+    lookup(&format!("metric.{id}")).unwrap_or(id)
+}
@@ -0,0 +1,51 @@
+use std::sync::mpsc::{Receiver, Sender};
+
+use anyhow::Result;
+
+use crate::{counter::Event, get_counter};
+
+mod replay;
+#[cfg(windows)]
+mod win32;
+
+#[cfg(target_os = "linux")]
+mod linux;
+
+pub use replay::Trace;
+#[cfg(windows)]
+pub use win32::HookSource;
+
+#[cfg(target_os = "linux")]
+pub use linux::EvdevSource;
+
+/// 输入事件来源
+///
+/// 采集到的键盘、鼠标事件转换为`Event`后通过channel发送, 由`consume`交给计数器处理,
+/// 计数逻辑因此不依赖具体平台的接口
+pub trait InputSource {
+    /// 开始采集并发送事件, 阻塞直到采集结束
+    fn run(&mut self, sender: Sender<Event>) -> Result<()>;
+}
+
+/// 当前平台默认的输入来源
+#[cfg(windows)]
+pub fn default_source() -> Result<Box<dyn InputSource>> {
+    Ok(Box::new(HookSource))
+}
+
+#[cfg(target_os = "linux")]
+pub fn default_source() -> Result<Box<dyn InputSource>> {
+    Ok(Box::new(EvdevSource))
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn default_source() -> Result<Box<dyn InputSource>> {
+    Err(anyhow::anyhow!("当前平台没有可用的输入来源!"))
+}
+
+/// 接收事件并计数, 直到所有发送端关闭
+pub fn consume(receiver: Receiver<Event>) {
+    for event in receiver {
+        get_counter().recv(event);
+    }
+}
@@ -0,0 +1,366 @@
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{mpsc::Sender, Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use evdev::{AbsoluteAxisType, Device, InputEventKind, Key, PropType, RelativeAxisType};
+
+use super::InputSource;
+use crate::{
+    counter::{Event, KeyEvent, MouseEvent, Point},
+    keys::VK_NUMPAD_ENTER,
+};
+
+/// 重新查找设备的间隔, 用于读取新插入和重新连接的设备
+const SCAN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 读不到屏幕大小时使用的大小
+const DEFAULT_SCREEN_SIZE: (i32, i32) = (1920, 1080);
+
+/// 读取/dev/input/event*设备的输入来源
+///
+/// 需要当前用户有读取/dev/input的权限(一般加入input用户组即可)
+///
+/// 鼠标只上报相对位移, 指针位置是从屏幕中心开始累计的位移, 限制在屏幕范围内。
+/// 触摸板上报手指的绝对坐标, 按手指移动的距离换算为位移, 触摸板的宽度对应屏幕宽度; 触摸屏的坐标直接对应屏幕位置。
+/// 位移没有经过系统的指针加速, 多显示器时只按第一个显示器计算, 因此点击热力图和双击距离只是近似值。
+/// 触摸板的轻触点击由桌面环境识别, 设备不会上报, 只统计按下触摸板的点击
+pub struct EvdevSource;
+
+impl InputSource for EvdevSource {
+    /// 定时查找新的设备, 读取新插入或拔出后重新连接的设备, 启动时没有可读取的设备才返回错误
+    fn run(&mut self, sender: Sender<Event>) -> Result<()> {
+        let (width, height) = screen_size().unwrap_or(DEFAULT_SCREEN_SIZE);
+        //多个设备共用一个指针位置
+        let position = Arc::new(Mutex::new(Point {
+            x: width / 2,
+            y: height / 2,
+        }));
+        //正在读取的设备路径, 设备断开后移除
+        let opened = Arc::new(Mutex::new(HashSet::new()));
+        //已经打开过但不是键盘、鼠标或触摸板的设备路径, 路径消失后移除
+        let mut ignored = HashSet::new();
+        let mut started = false;
+        loop {
+            let paths = device_paths();
+            ignored.retain(|path| paths.contains(path));
+            for path in paths {
+                //只打开新出现的设备
+                if ignored.contains(&path) || opened.lock().unwrap().contains(&path) {
+                    continue;
+                }
+                let device = match Device::open(&path) {
+                    Ok(device) if is_input_device(&device) => device,
+                    _ => {
+                        ignored.insert(path);
+                        continue;
+                    }
+                };
+                opened.lock().unwrap().insert(path.clone());
+                let sender = sender.clone();
+                let position = position.clone();
+                let opened = opened.clone();
+                std::thread::spawn(move || {
+                    if let Err(err) = read_device(device, sender, position, (width, height)) {
+                        eprintln!("读取输入设备{}失败: {err}", path.display());
+                    }
+                    opened.lock().unwrap().remove(&path);
+                });
+            }
+            if !started && opened.lock().unwrap().is_empty() {
+                return Err(anyhow!(
+                    "没有可读取的输入设备, 请检查当前用户是否有/dev/input的读取权限!"
+                ));
+            }
+            started = true;
+            std::thread::sleep(SCAN_INTERVAL);
+        }
+    }
+}
+
+/// /dev/input中所有event*设备的路径
+fn device_paths() -> HashSet<PathBuf> {
+    std::fs::read_dir("/dev/input")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with("event"))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// 读取第一个显示器的分辨率
+fn screen_size() -> Option<(i32, i32)> {
+    let size = std::fs::read_to_string("/sys/class/graphics/fb0/virtual_size").ok()?;
+    let (width, height) = size.trim().split_once(',')?;
+    let size = (width.parse().ok()?, height.parse().ok()?);
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+/// 键盘、鼠标, 以及有左键的绝对坐标设备(触摸板、触摸屏)
+fn is_input_device(device: &Device) -> bool {
+    let keys = device.supported_keys();
+    let keyboard = keys.is_some_and(|keys| keys.contains(Key::KEY_A));
+    let mouse = device
+        .supported_relative_axes()
+        .is_some_and(|axes| axes.contains(RelativeAxisType::REL_X));
+    let touch = device
+        .supported_absolute_axes()
+        .is_some_and(|axes| axes.contains(AbsoluteAxisType::ABS_X))
+        && keys.is_some_and(|keys| keys.contains(Key::BTN_LEFT) || keys.contains(Key::BTN_TOUCH));
+    keyboard || mouse || touch
+}
+
+/// 绝对坐标轴的一个方向
+struct AbsAxis {
+    minimum: i32,
+    /// 每个坐标单位对应的屏幕像素数
+    scale: f64,
+    /// 触摸板上一次的坐标, 手指离开后清空, 避免再次放下时指针跳动
+    last: Option<i32>,
+    /// 触摸板位移不足1像素的部分
+    remainder: f64,
+}
+
+impl AbsAxis {
+    /// 坐标范围对应屏幕的`screen`个像素, 设备没有这个坐标轴时返回None
+    fn new(device: &Device, axis: AbsoluteAxisType, screen: i32) -> Option<Self> {
+        if !device
+            .supported_absolute_axes()
+            .is_some_and(|axes| axes.contains(axis))
+        {
+            return None;
+        }
+        let info = device.get_abs_state().ok()?[axis.0 as usize];
+        let range = (info.maximum - info.minimum).max(1);
+        Some(Self {
+            minimum: info.minimum,
+            scale: screen as f64 / range as f64,
+            last: None,
+            remainder: 0.,
+        })
+    }
+
+    /// 触摸屏的坐标对应的屏幕位置
+    fn position(&self, value: i32) -> i32 {
+        ((value - self.minimum) as f64 * self.scale) as i32
+    }
+
+    /// 触摸板与上一次坐标之间的位移(像素)
+    fn delta(&mut self, value: i32) -> i32 {
+        let Some(last) = self.last.replace(value) else {
+            return 0;
+        };
+        self.remainder += (value - last) as f64 * self.scale;
+        let pixels = self.remainder.trunc();
+        self.remainder -= pixels;
+        pixels as i32
+    }
+
+    fn release(&mut self) {
+        self.last = None;
+        self.remainder = 0.;
+    }
+}
+
+fn read_device(
+    mut device: Device,
+    sender: Sender<Event>,
+    position: Arc<Mutex<Point>>,
+    (width, height): (i32, i32),
+) -> Result<()> {
+    //触摸屏直接对应屏幕位置, 触摸板和鼠标一样按位移移动指针
+    let direct = device.properties().contains(PropType::DIRECT);
+    let mut abs_x = AbsAxis::new(&device, AbsoluteAxisType::ABS_X, width);
+    let mut abs_y = AbsAxis::new(&device, AbsoluteAxisType::ABS_Y, height);
+    let (mut dx, mut dy) = (0, 0);
+    let (mut target_x, mut target_y) = (None, None);
+    loop {
+        for event in device.fetch_events()? {
+            let value = event.value();
+            let mut events = vec![];
+            match event.kind() {
+                InputEventKind::Key(key) => {
+                    let mouse_event = match (key, value) {
+                        (Key::BTN_LEFT, 1) => Some(MouseEvent::MouseLeftBUttonDown),
+                        (Key::BTN_LEFT, 0) => Some(MouseEvent::MouseLeftButtonUp),
+                        (Key::BTN_RIGHT, 1) => Some(MouseEvent::MouseRightButtonDown),
+                        (Key::BTN_RIGHT, 0) => Some(MouseEvent::MouseRightButtonUp),
+                        (Key::BTN_MIDDLE, 1) => Some(MouseEvent::MouseMiddleButtonDown),
+                        (Key::BTN_MIDDLE, 0) => Some(MouseEvent::MouseMiddleButtonUp),
+                        _ => None,
+                    };
+                    if key == Key::BTN_TOUCH && value == 0 {
+                        //手指离开触摸板
+                        abs_x
+                            .iter_mut()
+                            .chain(abs_y.iter_mut())
+                            .for_each(AbsAxis::release);
+                    } else if let Some(mouse_event) = mouse_event {
+                        events.push(Event::MouseEvent((mouse_event, current(&position))));
+                    } else if let Some(code) = to_virtual_key(key.code()) {
+                        //1按下, 2长按自动重复(与Windows一致按按下处理), 0抬起
+                        match value {
+                            1 | 2 => events.push(Event::KeyEvent(KeyEvent::KeyPress(code))),
+                            0 => events.push(Event::KeyEvent(KeyEvent::KeyUp(code))),
+                            _ => (),
+                        }
+                    }
+                }
+                InputEventKind::RelAxis(RelativeAxisType::REL_X) => dx += value,
+                InputEventKind::RelAxis(RelativeAxisType::REL_Y) => dy += value,
+                InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL) => {
+                    events.push(Event::MouseEvent((
+                        MouseEvent::MouseWheelRouting,
+                        current(&position),
+                    )));
+                }
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_X) => match &mut abs_x {
+                    Some(axis) if direct => target_x = Some(axis.position(value)),
+                    Some(axis) => dx += axis.delta(value),
+                    None => (),
+                },
+                InputEventKind::AbsAxis(AbsoluteAxisType::ABS_Y) => match &mut abs_y {
+                    Some(axis) if direct => target_y = Some(axis.position(value)),
+                    Some(axis) => dy += axis.delta(value),
+                    None => (),
+                },
+                InputEventKind::Synchronization(_)
+                    if dx != 0 || dy != 0 || target_x.is_some() || target_y.is_some() =>
+                {
+                    //一组位移或坐标上报完毕, 合并为一次移动
+                    let point = {
+                        let mut position = position.lock().unwrap();
+                        let x = target_x.take().unwrap_or(position.x) + dx;
+                        let y = target_y.take().unwrap_or(position.y) + dy;
+                        position.x = x.clamp(0, width - 1);
+                        position.y = y.clamp(0, height - 1);
+                        Point {
+                            x: position.x,
+                            y: position.y,
+                        }
+                    };
+                    events.push(Event::MouseEvent((MouseEvent::MouseMove, point)));
+                    dx = 0;
+                    dy = 0;
+                }
+                _ => (),
+            }
+            for event in events {
+                sender.send(event)?;
+            }
+        }
+    }
+}
+
+fn current(position: &Mutex<Point>) -> Point {
+    let position = position.lock().unwrap();
+    Point {
+        x: position.x,
+        y: position.y,
+    }
+}
+
+/// Linux按键码转换为Windows虚拟键码, 计数器统一使用虚拟键码
+fn to_virtual_key(code: u16) -> Option<u32> {
+    let vk = match code {
+        1 => 0x1B,                          // Esc
+        2..=10 => 0x31 + code as u32 - 2,   // 1-9
+        11 => 0x30,                         // 0
+        12 => 0xBD,                         // -
+        13 => 0xBB,                         // =
+        14 => 0x08,                         // Backspace
+        15 => 0x09,                         // Tab
+        16 => 0x51,                         // Q
+        17 => 0x57,                         // W
+        18 => 0x45,                         // E
+        19 => 0x52,                         // R
+        20 => 0x54,                         // T
+        21 => 0x59,                         // Y
+        22 => 0x55,                         // U
+        23 => 0x49,                         // I
+        24 => 0x4F,                         // O
+        25 => 0x50,                         // P
+        26 => 0xDB,                         // [
+        27 => 0xDD,                         // ]
+        28 => 0x0D,                         // Enter
+        29 => 0xA2,                         // 左Ctrl
+        30 => 0x41,                         // A
+        31 => 0x53,                         // S
+        32 => 0x44,                         // D
+        33 => 0x46,                         // F
+        34 => 0x47,                         // G
+        35 => 0x48,                         // H
+        36 => 0x4A,                         // J
+        37 => 0x4B,                         // K
+        38 => 0x4C,                         // L
+        39 => 0xBA,                         // ;
+        40 => 0xDE,                         // '
+        41 => 0xC0,                         // `
+        42 => 0xA0,                         // 左Shift
+        43 => 0xDC,                         // \
+        44 => 0x5A,                         // Z
+        45 => 0x58,                         // X
+        46 => 0x43,                         // C
+        47 => 0x56,                         // V
+        48 => 0x42,                         // B
+        49 => 0x4E,                         // N
+        50 => 0x4D,                         // M
+        51 => 0xBC,                         // ,
+        52 => 0xBE,                         // .
+        53 => 0xBF,                         // /
+        54 => 0xA1,                         // 右Shift
+        55 => 0x6A,                         // 小键盘*
+        56 => 0xA4,                         // 左Alt
+        57 => 0x20,                         // 空格
+        58 => 0x14,                         // CapsLock
+        59..=68 => 0x70 + code as u32 - 59, // F1-F10
+        69 => 0x90,                         // NumLock
+        70 => 0x91,                         // ScrollLock
+        71 => 0x67,                         // 小键盘7
+        72 => 0x68,                         // 小键盘8
+        73 => 0x69,                         // 小键盘9
+        74 => 0x6D,                         // 小键盘-
+        75 => 0x64,                         // 小键盘4
+        76 => 0x65,                         // 小键盘5
+        77 => 0x66,                         // 小键盘6
+        78 => 0x6B,                         // 小键盘+
+        79 => 0x61,                         // 小键盘1
+        80 => 0x62,                         // 小键盘2
+        81 => 0x63,                         // 小键盘3
+        82 => 0x60,                         // 小键盘0
+        83 => 0x6E,                         // 小键盘.
+        86 => 0xE2,                         // ISO键盘的额外按键
+        87 => 0x7A,                         // F11
+        88 => 0x7B,                         // F12
+        96 => VK_NUMPAD_ENTER,              // 小键盘Enter
+        97 => 0xA3,                         // 右Ctrl
+        98 => 0x6F,                         // 小键盘/
+        99 => 0x2C,                         // PrintScreen
+        100 => 0xA5,                        // 右Alt
+        102 => 0x24,                        // Home
+        103 => 0x26,                        // ↑
+        104 => 0x21,                        // PageUp
+        105 => 0x25,                        // ←
+        106 => 0x27,                        // →
+        107 => 0x23,                        // End
+        108 => 0x28,                        // ↓
+        109 => 0x22,                        // PageDown
+        110 => 0x2D,                        // Insert
+        111 => 0x2E,                        // Delete
+        119 => 0x13,                        // Pause
+        125 => 0x5B,                        // 左Win
+        126 => 0x5C,                        // 右Win
+        127 => 0x5D,                        // 菜单键
+        _ => return None,
+    };
+    Some(vk)
+}
@@ -0,0 +1,347 @@
+use std::{
+    path::Path,
+    sync::{mpsc::Sender, Arc},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset, TimeZone};
+
+use super::InputSource;
+use crate::{
+    clock::{Clock, ManualClock, SystemClock},
+    counter::{Counter, Event, KeyEvent, MouseEvent, Point},
+};
+
+/// 事件记录, 用于回放
+///
+/// 文件每行一个事件, `#`开头为注释, 时间为毫秒时间戳:
+///
+/// ```text
+/// # 时间 事件 参数
+/// timezone +08:00
+/// 0 key_down 162
+/// 30 key_up 162
+/// 100 left_down 10 20
+/// 150 left_up 10 20
+/// 200 wheel 10 20
+/// 300 move 15 25
+/// ```
+///
+/// 键盘事件为`key_down`/`key_up`加虚拟键码, 鼠标事件为`move`、`left_down`、`left_up`、
+/// `right_down`、`right_up`、`middle_down`、`middle_up`、`wheel`加坐标。
+/// `timezone`行设置其后事件的时区偏移(默认UTC), 可用于模拟时区变化和夏令时切换
+#[derive(Debug, Default)]
+pub struct Trace {
+    pub events: Vec<(DateTime<FixedOffset>, Event)>,
+}
+
+impl Trace {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut events = vec![];
+        let mut offset = FixedOffset::east_opt(0).unwrap();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = || anyhow!("第{}行格式错误: {}", index + 1, line);
+            if let Some(timezone) = line.strip_prefix("timezone") {
+                offset = parse_offset(timezone.trim()).ok_or_else(error)?;
+                continue;
+            }
+            let (time, event) = parse_line(line).ok_or_else(error)?;
+            let time = offset.timestamp_millis_opt(time).single().ok_or_else(error)?;
+            events.push((time, event));
+        }
+        Ok(Self { events })
+    }
+
+    /// 创建计数器并按记录的时间处理所有事件, 结果与实际运行时间无关
+    ///
+    /// 计数器使用从第一个事件的时间开始的手动时钟, 今天的日期也由此确定
+    pub fn replay(&self) -> Counter {
+        let start = self
+            .events
+            .first()
+            .map_or_else(|| SystemClock.now(), |(time, _)| *time);
+        let clock = Arc::new(ManualClock::new(start));
+        let mut counter = Counter::with_clock(clock.clone());
+        for (time, event) in &self.events {
+            clock.set(*time);
+            counter.recv(event.clone());
+        }
+        counter
+    }
+}
+
+impl InputSource for Trace {
+    /// 按记录的时间间隔实时发送事件
+    fn run(&mut self, sender: Sender<Event>) -> Result<()> {
+        let mut last_time: Option<DateTime<FixedOffset>> = None;
+        for (time, event) in self.events.drain(..) {
+            if let Some(last_time) = last_time {
+                let delay = (time - last_time).num_milliseconds();
+                if delay > 0 {
+                    std::thread::sleep(Duration::from_millis(delay as u64));
+                }
+            }
+            last_time = Some(time);
+            sender.send(event)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Option<(i64, Event)> {
+    let mut fields = line.split_whitespace();
+    let time = fields.next()?.parse().ok()?;
+    let name = fields.next()?;
+    let args = fields
+        .map(|field| field.parse::<i32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    let event = match (name, &args[..]) {
+        ("key_down", [code]) => Event::KeyEvent(KeyEvent::KeyPress(*code as u32)),
+        ("key_up", [code]) => Event::KeyEvent(KeyEvent::KeyUp(*code as u32)),
+        (name, [x, y]) => {
+            let mouse_event = match name {
+                "move" => MouseEvent::MouseMove,
+                "left_down" => MouseEvent::MouseLeftBUttonDown,
+                "left_up" => MouseEvent::MouseLeftButtonUp,
+                "right_down" => MouseEvent::MouseRightButtonDown,
+                "right_up" => MouseEvent::MouseRightButtonUp,
+                "middle_down" => MouseEvent::MouseMiddleButtonDown,
+                "middle_up" => MouseEvent::MouseMiddleButtonUp,
+                "wheel" => MouseEvent::MouseWheelRouting,
+                _ => return None,
+            };
+            Event::MouseEvent((mouse_event, Point { x: *x, y: *y }))
+        }
+        _ => return None,
+    };
+    Some((time, event))
+}
+
+/// 解析`+08:00`、`-05:00`格式的时区偏移
+fn parse_offset(text: &str) -> Option<FixedOffset> {
+    let (sign, text) = if let Some(text) = text.strip_prefix('+') {
+        (1, text)
+    } else {
+        (-1, text.strip_prefix('-')?)
+    };
+    let (hours, minutes) = text.split_once(':')?;
+    let seconds = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+    FixedOffset::east_opt(sign * seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Trace;
+    use crate::counter::Counter;
+
+    fn replay(trace: &str) -> Counter {
+        Trace::parse(trace).unwrap().replay()
+    }
+
+    fn count(counter: &Counter, name: &str) -> u128 {
+        counter.maps.get(name).copied().unwrap_or(0)
+    }
+
+    #[test]
+    fn double_click() {
+        let counter = replay(
+            "
+            timezone +08:00
+            1711778400000 left_down 100 100
+            1711778400050 left_up 100 100
+            # 间隔300毫秒, 距离小于4像素
+            1711778400300 left_down 102 101
+            1711778400350 left_up 102 101
+            # 间隔700毫秒
+            1711778401000 left_down 102 101
+            1711778401050 left_up 102 101
+            # 间隔200毫秒, 距离太远
+            1711778401200 left_down 150 150
+            1711778401250 left_up 150 150
+            ",
+        );
+        assert_eq!(count(&counter, "mouse_left_click"), 4);
+        assert_eq!(count(&counter, "mouse_double_click"), 1);
+    }
+
+    #[test]
+    fn wheel_and_move_throttle() {
+        let counter = replay(
+            "
+            1711778400000 wheel 10 10
+            1711778400100 wheel 10 10
+            1711778400800 wheel 10 10
+            1711778400801 wheel 10 10
+            1711778401000 wheel 10 10
+            1711778401700 wheel 10 10
+            1711778410000 move 10 10
+            1711778410799 move 11 10
+            1711778410800 move 12 10
+            1711778410801 move 13 10
+            1711778411500 move 14 10
+            ",
+        );
+        //间隔超过800毫秒才计数, 间隔内的多次只计一次
+        assert_eq!(count(&counter, "mouse_wheel"), 3);
+        assert_eq!(count(&counter, "mouse_move"), 2);
+    }
+
+    #[test]
+    fn ctrl_and_alt_chords() {
+        let counter = replay(
+            "
+            # 左Ctrl+C
+            1711778400000 key_down 162
+            1711778400050 key_down 67
+            1711778400100 key_up 67
+            1711778400150 key_up 162
+            # 右Ctrl+V
+            1711778400200 key_down 163
+            1711778400250 key_down 86
+            1711778400300 key_up 86
+            1711778400350 key_up 163
+            # Ctrl+Shift+C不计入Ctrl+C
+            1711778400400 key_down 162
+            1711778400450 key_down 160
+            1711778400500 key_down 67
+            1711778400550 key_up 67
+            1711778400600 key_up 160
+            1711778400650 key_up 162
+            # 按住Alt切换两次
+            1711778400700 key_down 164
+            1711778400750 key_down 9
+            1711778400800 key_up 9
+            1711778400850 key_down 9
+            1711778400900 key_up 9
+            1711778400950 key_up 164
+            # 松开Ctrl后单独按C
+            1711778401000 key_down 67
+            1711778401050 key_up 67
+            ",
+        );
+        assert_eq!(count(&counter, "CTRL + C"), 1);
+        assert_eq!(count(&counter, "CTRL + V"), 1);
+        assert_eq!(count(&counter, "ALT + TAB"), 2);
+        assert_eq!(count(&counter, "tab"), 2);
+        assert_eq!(count(&counter, "keystrokes"), 11);
+    }
+
+    #[test]
+    fn lost_modifier_release_expires() {
+        let counter = replay(
+            "
+            # Win+L锁屏, 收不到Win的松开事件
+            1711778400000 key_down 91
+            1711778400050 key_down 76
+            1711778400100 key_up 76
+            # 解锁后按Ctrl+C
+            1711778460000 key_down 162
+            1711778460050 key_down 67
+            1711778460100 key_up 67
+            1711778460150 key_up 162
+            ",
+        );
+        assert_eq!(count(&counter, "CTRL + C"), 1);
+    }
+
+    #[test]
+    fn modifier_timeout() {
+        let counter = replay(
+            "
+            # 按住Ctrl自动重复, 一直有输入, 超过3秒后仍然是Ctrl+C
+            1711778400000 key_down 162
+            1711778401000 key_down 162
+            1711778402000 key_down 162
+            1711778403000 key_down 162
+            1711778403500 key_down 67
+            1711778403550 key_up 67
+            1711778403600 key_up 162
+            # 丢失了Ctrl的松开事件, 2.9秒后按C仍然是Ctrl+C
+            1711778410000 key_down 162
+            1711778412900 key_down 67
+            1711778412950 key_up 67
+            # 超过3秒没有任何输入, Ctrl视为已松开
+            1711778416000 key_down 67
+            1711778416050 key_up 67
+            ",
+        );
+        assert_eq!(count(&counter, "CTRL + C"), 2);
+        assert_eq!(count(&counter, "key_presses"), 5);
+        assert_eq!(count(&counter, "key_repeats"), 3);
+    }
+
+    #[test]
+    fn modifier_held_past_repeat_timeout() {
+        let counter = replay(
+            "
+            # 按住Ctrl, 超过1.5秒没有收到重复按下, 再次按下视为重新按下
+            1711778400000 key_down 162
+            1711778402000 key_down 162
+            1711778402500 key_down 67
+            1711778402550 key_up 67
+            1711778402600 key_up 162
+            ",
+        );
+        assert_eq!(count(&counter, "CTRL + C"), 1);
+        assert_eq!(count(&counter, "key_presses"), 3);
+        assert_eq!(count(&counter, "key_repeats"), 0);
+        //按住时长从重新按下时开始计算
+        assert_eq!(counter.hold.count, 2);
+        assert_eq!(counter.hold.duration, 600 + 50);
+    }
+
+    #[test]
+    fn key_repeats_and_hold() {
+        let counter = replay(
+            "
+            # 按住A, 500毫秒后开始自动重复
+            1711778400000 key_down 65
+            1711778400500 key_down 65
+            1711778400533 key_down 65
+            1711778400566 key_down 65
+            1711778400600 key_down 65
+            1711778400620 key_up 65
+            # 单击B
+            1711778401000 key_down 66
+            1711778401080 key_up 66
+            # 丢失了C的松开事件, 超过1.5秒后再次按下不算自动重复
+            1711778402000 key_down 67
+            1711778404000 key_down 67
+            1711778404100 key_up 67
+            ",
+        );
+        assert_eq!(count(&counter, "keystrokes"), 8);
+        assert_eq!(count(&counter, "key_presses"), 4);
+        assert_eq!(count(&counter, "key_repeats"), 4);
+        //按住时长从按下到松开, 自动重复不单独计算
+        let hold = &counter.hold;
+        assert_eq!(hold.count, 3);
+        assert_eq!(hold.duration, 620 + 80 + 100);
+        assert_eq!(hold.buckets, [0, 1, 1, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(counter.today.hold, counter.hold);
+    }
+
+    #[test]
+    fn replay_uses_trace_date() {
+        let counter = replay(
+            "
+            timezone +08:00
+            1711778400000 key_down 65
+            1711778400050 key_up 65
+            ",
+        );
+        assert_eq!(counter.today.date, "2024-03-30");
+        assert_eq!(counter.today.maps.get("keystrokes"), Some(&1));
+        assert!(counter.history.days.is_empty());
+    }
+}
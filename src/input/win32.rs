@@ -0,0 +1,117 @@
+use std::{ptr::null_mut, sync::mpsc::Sender};
+
+use anyhow::Result;
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+    UI::WindowsAndMessaging::{
+        CallNextHookEx, DispatchMessageW, GetMessageW, TranslateMessage, KBDLLHOOKSTRUCT,
+        LLKHF_EXTENDED, MSG, MSLLHOOKSTRUCT,
+    },
+};
+
+use super::InputSource;
+use crate::{
+    counter::{Event, KeyEvent, MouseEvent, Point},
+    keys::VK_NUMPAD_ENTER,
+    tools::{set_keyboard_hook, set_mouse_hook, KEYBOARD_HOOK, MOUSE_HOOK},
+};
+
+/// 钩子回调中使用的发送端
+static mut SENDER: *mut Sender<Event> = null_mut();
+
+fn send(event: Event) {
+    unsafe {
+        if !SENDER.is_null() {
+            let _ = (*SENDER).send(event);
+        }
+    }
+}
+
+/// Win32低级键盘、鼠标钩子
+pub struct HookSource;
+
+impl InputSource for HookSource {
+    fn run(&mut self, sender: Sender<Event>) -> Result<()> {
+        unsafe {
+            SENDER = Box::into_raw(Box::new(sender));
+        }
+
+        set_keyboard_hook(keyboard_hook_proc)?;
+        set_mouse_hook(mouse_hook_proc)?;
+
+        //钩子需要在安装它的线程上运行消息循环
+        unsafe {
+            let mut msg = MSG::default();
+            while GetMessageW(&mut msg, HWND(0), 0, 0).0 > 0 {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 按扩展键标志区分虚拟键码相同的按键
+///
+/// 小键盘Enter是扩展键, 使用单独的键码; 关闭NumLock(或按住Shift)时小键盘发送方向键和编辑键的
+/// 虚拟键码但不是扩展键, 转换为对应的小键盘键码
+fn key_code(data: &KBDLLHOOKSTRUCT) -> u32 {
+    let extended = data.flags.0 & LLKHF_EXTENDED.0 != 0;
+    match (data.vkCode, extended) {
+        (0x0D, true) => VK_NUMPAD_ENTER,
+        (0x2D, false) => 0x60, // Insert -> 小键盘0
+        (0x23, false) => 0x61, // End -> 小键盘1
+        (0x28, false) => 0x62, // ↓ -> 小键盘2
+        (0x22, false) => 0x63, // PageDown -> 小键盘3
+        (0x25, false) => 0x64, // ← -> 小键盘4
+        (0x0C, false) => 0x65, // Clear -> 小键盘5
+        (0x27, false) => 0x66, // → -> 小键盘6
+        (0x24, false) => 0x67, // Home -> 小键盘7
+        (0x26, false) => 0x68, // ↑ -> 小键盘8
+        (0x21, false) => 0x69, // PageUp -> 小键盘9
+        (0x2E, false) => 0x6E, // Delete -> 小键盘.
+        (code, _) => code,
+    }
+}
+
+unsafe extern "system" fn keyboard_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    let data = lparam.0 as *const KBDLLHOOKSTRUCT;
+    if !data.is_null() {
+        let data: &KBDLLHOOKSTRUCT = &*data;
+
+        match wparam {
+            WPARAM(0x100) => {
+                //普通键按下
+                send(Event::KeyEvent(KeyEvent::KeyPress(key_code(data))));
+            }
+            WPARAM(0x101) => {
+                //普通键抬起
+                send(Event::KeyEvent(KeyEvent::KeyUp(key_code(data))));
+            }
+            WPARAM(0x104) => {
+                //系统键按下
+                send(Event::KeyEvent(KeyEvent::KeyPress(key_code(data))));
+            }
+            WPARAM(0x105) => {
+                //系统键抬起
+                send(Event::KeyEvent(KeyEvent::KeyUp(key_code(data))));
+            }
+            _ => (),
+        };
+    }
+    CallNextHookEx(KEYBOARD_HOOK, code, wparam, lparam)
+}
+
+unsafe extern "system" fn mouse_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    let data = lparam.0 as *const MSLLHOOKSTRUCT;
+    if !data.is_null() {
+        if let Ok(mouse_event) = MouseEvent::try_from(wparam.0 as i32) {
+            let point = Point {
+                x: (*data).pt.x,
+                y: (*data).pt.y,
+            };
+            send(Event::MouseEvent((mouse_event, point)));
+        }
+    }
+    CallNextHookEx(MOUSE_HOOK, code, wparam, lparam)
+}
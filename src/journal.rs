@@ -0,0 +1,317 @@
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, TimeZone};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex, TryLockError},
+    thread::JoinHandle,
+};
+
+use crate::{
+    clock::ManualClock, counter::Counter, get_counter, storage::save_storage, tools::get_app_dir,
+};
+
+/// 增量按时段合并, 时段长度(毫秒)与5分钟的时段计数相同
+const SLOT_MILLIS: i64 = 5 * 60 * 1000;
+
+/// 一个时段内的计数增量
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Slot {
+    maps: HashMap<String, u128>,
+    keys: HashMap<u32, u128>,
+}
+
+/// 上次写入日志之后的计数增量, 只记录每个时段内各项计数的合计, 不记录按键的顺序
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Increments {
+    /// (时段开始的时间戳(毫秒), 时区偏移(秒)) => 计数增量
+    slots: BTreeMap<(i64, i32), Slot>,
+}
+
+impl Increments {
+    fn slot(&mut self, now: &DateTime<FixedOffset>) -> &mut Slot {
+        let time = now.timestamp_millis();
+        let start = time - time.rem_euclid(SLOT_MILLIS);
+        let offset = now.offset().local_minus_utc();
+        self.slots.entry((start, offset)).or_default()
+    }
+
+    pub fn add_count(&mut self, name: &str, count: u128, now: &DateTime<FixedOffset>) {
+        let maps = &mut self.slot(now).maps;
+        if let Some(val) = maps.get_mut(name) {
+            *val += count;
+        } else {
+            maps.insert(name.to_string(), count);
+        }
+    }
+
+    pub fn add_key(&mut self, code: u32, count: u128, now: &DateTime<FixedOffset>) {
+        *self.slot(now).keys.entry(code).or_insert(0) += count;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+/// 日志中的一条记录
+#[derive(Serialize, Deserialize)]
+struct Record {
+    /// 写入时间(毫秒), 保证递增, 保存的数据中的`timestamp`为其中已包含的最后一条记录的时间
+    time: i64,
+    increments: Increments,
+}
+
+/// 增量日志, 记录上次保存之后的计数增量
+///
+/// 每条记录为: 长度(u32小端) + CRC32校验和(u32小端) + bincode序列化的`Record`。
+/// 程序异常退出后, 启动时在保存的数据上累加日志中的增量, 保存完整数据后删除日志。
+/// 只记录计数、按键计数以及它们所在的时段, 点击位置、打字速度等统计只在完整保存时写入
+struct Journal {
+    file: Option<File>,
+}
+
+static JOURNAL: Mutex<Journal> = Mutex::new(Journal { file: None });
+
+pub fn get_journal_path() -> PathBuf {
+    let mut app_dir = get_app_dir().unwrap_or(PathBuf::from("./"));
+    app_dir.push("keyboard-counter.journal");
+    app_dir
+}
+
+fn encode_record(record: &Record) -> Result<Vec<u8>> {
+    let payload = bincode::serialize(record)?;
+    let mut data = (payload.len() as u32).to_le_bytes().to_vec();
+    data.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    data.extend_from_slice(&payload);
+    Ok(data)
+}
+
+impl Journal {
+    fn append(&mut self, record: &Record) -> Result<()> {
+        let data = encode_record(record)?;
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(get_journal_path())?,
+            ),
+        };
+        if let Err(err) = file.write_all(&data) {
+            self.file = None;
+            return Err(err.into());
+        }
+        Ok(())
+    }
+}
+
+fn lock() -> std::sync::MutexGuard<'static, Journal> {
+    JOURNAL.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// 下一条记录的时间, 系统时间调回时也保持递增
+fn next_time(counter: &mut Counter) -> i64 {
+    counter.timestamp = counter
+        .clock
+        .now()
+        .timestamp_millis()
+        .max(counter.timestamp + 1);
+    counter.timestamp
+}
+
+/// 取出上次写入之后的计数增量
+fn take_record(counter: &mut Counter) -> Option<Record> {
+    if counter.increments.is_empty() {
+        return None;
+    }
+    Some(Record {
+        time: next_time(counter),
+        increments: std::mem::take(&mut counter.increments),
+    })
+}
+
+/// 将上次写入之后的计数增量写入日志, 由窗口每秒调用, 写入文件时不锁定计数器
+///
+/// 正在后台保存时跳过, 增量留在计数器中下次写入, 避免窗口等待保存完成
+pub fn flush() -> Result<()> {
+    let mut journal = match JOURNAL.try_lock() {
+        Ok(journal) => journal,
+        Err(TryLockError::Poisoned(err)) => err.into_inner(),
+        Err(TryLockError::WouldBlock) => return Ok(()),
+    };
+    let record = take_record(&mut get_counter());
+    match record {
+        Some(record) => journal.append(&record),
+        None => Ok(()),
+    }
+}
+
+/// 保存完整数据后删除日志
+///
+/// 只在复制数据时锁定计数器, 保存期间不影响计数。
+/// 保存的数据中记录了最新的时间, 删除日志前崩溃时, 启动后会跳过日志中已经保存过的记录
+pub fn compact() -> Result<()> {
+    let mut journal = lock();
+    let (record, snapshot) = {
+        let mut counter = get_counter();
+        let record = take_record(&mut counter);
+        next_time(&mut counter);
+        (record, counter.clone())
+    };
+    //先写入日志, 保存失败时这些增量仍然可以恢复
+    if let Some(record) = record {
+        if let Err(err) = journal.append(&record) {
+            eprintln!("写入增量日志失败: {err}");
+        }
+    }
+    save_storage(&snapshot)?;
+    journal.file = None;
+    match fs::remove_file(get_journal_path()) {
+        Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// 在后台线程中保存完整数据, 出错时只记录日志
+pub fn compact_async() -> JoinHandle<()> {
+    std::thread::spawn(|| {
+        if let Err(err) = compact() {
+            eprintln!("保存数据失败: {err}");
+        }
+    })
+}
+
+/// 读取一条记录, 崩溃时最后一条记录可能没有写完整, 此时返回None
+fn read_record(reader: &mut &[u8]) -> Option<Record> {
+    let len = u32::from_le_bytes(reader.get(..4)?.try_into().ok()?) as usize;
+    let checksum = u32::from_le_bytes(reader.get(4..8)?.try_into().ok()?);
+    let payload = reader.get(8..8 + len)?;
+    if crc32fast::hash(payload) != checksum {
+        return None;
+    }
+    *reader = &reader[8 + len..];
+    bincode::deserialize(payload).ok()
+}
+
+/// 按时段累加计数, 计数器的时钟临时设为各时段的开始时间, 使今日计数和时段计数落在原来的时段
+fn apply(counter: &mut Counter, increments: Increments) {
+    for ((start, offset), slot) in increments.slots {
+        let time = FixedOffset::east_opt(offset)
+            .and_then(|offset| offset.timestamp_millis_opt(start).single());
+        let Some(time) = time else {
+            continue;
+        };
+        counter.clock = Arc::new(ManualClock::new(time));
+        for (name, count) in slot.maps {
+            counter.add_count_by(&name, count);
+        }
+        for (code, count) in slot.keys {
+            counter.add_key_by(code, count);
+        }
+    }
+}
+
+/// 在保存的数据上累加日志中还没有保存的记录, 返回累加的记录数量
+pub fn replay(counter: &mut Counter) -> Result<usize> {
+    let data = match fs::read(get_journal_path()) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(apply_records(counter, &data))
+}
+
+/// 累加时间晚于`counter.timestamp`的记录, 即上次保存之后写入的记录
+fn apply_records(counter: &mut Counter, data: &[u8]) -> usize {
+    let clock = counter.clock.clone();
+    let mut reader = data;
+    let mut count = 0;
+    while let Some(record) = read_record(&mut reader) {
+        if record.time <= counter.timestamp {
+            continue;
+        }
+        apply(counter, record.increments);
+        counter.timestamp = record.time;
+        count += 1;
+    }
+    counter.clock = clock;
+    counter.increments = Increments::default();
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::clock::{manual_counter, parse_time};
+
+    /// 按写入日志的方式生成一条记录
+    fn record(counter: &mut Counter) -> Vec<u8> {
+        encode_record(&take_record(counter).unwrap()).unwrap()
+    }
+
+    /// 两条记录, 第二条跨过午夜, 返回(日志, 写入日志的计数器)
+    fn journal() -> (Vec<u8>, Counter) {
+        let (mut counter, clock) = manual_counter("2024-01-01T23:58:00+08:00");
+        counter.add_count("keystrokes");
+        counter.add_key(65);
+        counter.add_count_by("mouse_distance", 42);
+        let mut data = record(&mut counter);
+        clock.set(parse_time("2024-01-02T00:01:00+08:00"));
+        counter.add_count("keystrokes");
+        counter.add_key(65);
+        data.extend(record(&mut counter));
+        (data, counter)
+    }
+
+    fn replayed(data: &[u8], timestamp: i64) -> (usize, Counter) {
+        let (mut counter, _) = manual_counter("2024-01-02T08:00:00+08:00");
+        counter.timestamp = timestamp;
+        (apply_records(&mut counter, data), counter)
+    }
+
+    #[test]
+    fn replay_restores_counts() {
+        let (data, written) = journal();
+        let (count, counter) = replayed(&data, 0);
+        assert_eq!(count, 2);
+        assert_eq!(counter.maps, written.maps);
+        assert_eq!(counter.keys, written.keys);
+        assert_eq!(counter.today.date, "2024-01-02");
+        assert_eq!(counter.today.maps["keystrokes"], 1);
+        let yesterday = counter
+            .history
+            .day(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        assert_eq!(yesterday.unwrap().maps["mouse_distance"], 42);
+        assert_eq!(counter.timestamp, written.timestamp);
+        assert!(counter.increments.is_empty());
+    }
+
+    #[test]
+    fn skip_saved_records() {
+        let (data, written) = journal();
+        //保存后删除日志前崩溃, 日志中的记录都已经保存过
+        let (count, counter) = replayed(&data, written.timestamp);
+        assert_eq!(count, 0);
+        assert!(counter.maps.is_empty());
+        //只保存了第一条记录
+        let (count, counter) = replayed(&data, written.timestamp - 1);
+        assert_eq!(count, 1);
+        assert_eq!(counter.maps["keystrokes"], 1);
+    }
+
+    #[test]
+    fn torn_tail() {
+        let (data, _) = journal();
+        let (count, counter) = replayed(&data[..data.len() - 3], 0);
+        assert_eq!(count, 1);
+        assert_eq!(counter.maps["keystrokes"], 1);
+        assert_eq!(counter.keys[&65], 1);
+    }
+}
@@ -0,0 +1,78 @@
+use std::borrow::Cow;
+
+/// 小键盘Enter的键码, 它的虚拟键码与Enter相同, 按扩展键标志区分后使用虚拟键码之外的值
+pub const VK_NUMPAD_ENTER: u32 = 0x10D;
+
+/// 虚拟键码对应的按键名称, 没有名称的按键显示为16进制键码
+pub fn key_name(code: u32) -> Cow<'static, str> {
+    let name = match code {
+        0x08 => "Backspace",
+        0x09 => "Tab",
+        0x0C => "Clear",
+        0x0D => "Enter",
+        0x10 => "Shift",
+        0x11 => "Ctrl",
+        0x12 => "Alt",
+        0x13 => "Pause",
+        0x14 => "CapsLock",
+        0x1B => "Esc",
+        0x20 => "Space",
+        0x21 => "PageUp",
+        0x22 => "PageDown",
+        0x23 => "End",
+        0x24 => "Home",
+        0x25 => "Left",
+        0x26 => "Up",
+        0x27 => "Right",
+        0x28 => "Down",
+        0x2C => "PrintScreen",
+        0x2D => "Insert",
+        0x2E => "Delete",
+        0x30..=0x39 | 0x41..=0x5A => {
+            return Cow::Owned(char::from_u32(code).unwrap_or('?').to_string())
+        }
+        0x5B => "LWin",
+        0x5C => "RWin",
+        0x5D => "Menu",
+        0x60..=0x69 => return Cow::Owned(format!("Num{}", code - 0x60)),
+        0x6A => "Num*",
+        0x6B => "Num+",
+        0x6D => "Num-",
+        0x6E => "Num.",
+        0x6F => "Num/",
+        VK_NUMPAD_ENTER => "NumEnter",
+        0x70..=0x87 => return Cow::Owned(format!("F{}", code - 0x70 + 1)),
+        0x90 => "NumLock",
+        0x91 => "ScrollLock",
+        0xA0 => "LShift",
+        0xA1 => "RShift",
+        0xA2 => "LCtrl",
+        0xA3 => "RCtrl",
+        0xA4 => "LAlt",
+        0xA5 => "RAlt",
+        0xBA => ";",
+        0xBB => "=",
+        0xBC => ",",
+        0xBD => "-",
+        0xBE => ".",
+        0xBF => "/",
+        0xC0 => "`",
+        0xDB => "[",
+        0xDC => "\\",
+        0xDD => "]",
+        0xDE => "'",
+        0xE2 => "IntlBackslash",
+        _ => return Cow::Owned(format!("0x{code:02X}")),
+    };
+    Cow::Borrowed(name)
+}
+
+/// 按键名称对应的虚拟键码, 不区分大小写, 也可以直接写`0x41`这样的16进制键码
+pub fn parse_key(name: &str) -> Option<u32> {
+    if let Some(hex) = name.strip_prefix("0x").or_else(|| name.strip_prefix("0X")) {
+        return u32::from_str_radix(hex, 16).ok();
+    }
+    (1..=0xFE)
+        .chain([VK_NUMPAD_ENTER])
+        .find(|code| key_name(*code).eq_ignore_ascii_case(name))
+}
@@ -1,104 +1,199 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use anyhow::Result;
-use chrono::Utc;
-use counter::{Counter, Event, KeyEvent, MouseEvent, Point, Today};
-use std::{collections::HashMap, ptr::null_mut};
-use tools::{read_storage, set_keyboard_hook, set_mouse_hook, KEYBOARD_HOOK, MOUSE_HOOK};
-use windows::Win32::{
-    Foundation::{HWND, LPARAM, LRESULT, WPARAM},
-    UI::WindowsAndMessaging::{
-        CallNextHookEx, DispatchMessageW, GetMessageW, TranslateMessage, KBDLLHOOKSTRUCT, MSG,
-        MSLLHOOKSTRUCT,
-    },
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use config::{read_config, Config};
+use counter::{Counter, Today};
+use i18n::{metric_label, set_language, tr};
+use input::Trace;
+use std::{
+    collections::HashMap,
+    sync::{mpsc::channel, Mutex, MutexGuard, OnceLock},
 };
+use storage::{get_storage_path, read_storage, replace_storage, salvage, NewerVersion};
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_OK};
 
+mod chord;
+mod clock;
+mod config;
+mod correction;
 mod counter;
+mod heatmap;
+mod history;
+mod hold;
+mod i18n;
+mod input;
+mod journal;
+mod keys;
+mod sequence;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod storage;
+mod theme;
+mod timeline;
 mod tools;
+mod typing;
 mod window;
 
-pub static mut COUNTER: *mut Counter = null_mut();
+/// 计数器, 输入线程计数, 窗口线程绘制、修改设置和保存
+static COUNTER: OnceLock<Mutex<Counter>> = OnceLock::new();
 
-pub fn get_counter() -> &'static Counter {
-    unsafe { &*COUNTER }
-}
-pub fn get_counter_mut() -> &'static mut Counter {
-    unsafe { &mut *COUNTER }
+/// 锁定计数器, 同一语句中不要再次调用
+pub fn get_counter() -> MutexGuard<'static, Counter> {
+    COUNTER
+        .get()
+        .expect("计数器还没有初始化")
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
 }
 
 fn main() -> Result<()> {
-    let counter = Box::new(read_storage().unwrap_or(Counter {
-        timestamp: Utc::now().timestamp_millis(),
-        maps: HashMap::new(),
-        today: Today::new(),
-        ctrl_press: false,
-        last_mouse_click_event: (0, Point::default()),
-        last_mouse_wheel_time: 0,
-        last_mouse_move_time: 0,
-        alt_press: false,
-        show_today: false,
-    }));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let config = read_config().unwrap_or_else(|err| {
+        eprintln!("读取配置文件失败: {err}");
+        Default::default()
+    });
+    //命令行输出也使用配置的语言
+    set_language(config.language);
+    match &args[..] {
+        [command, path] if command == "replay" => return replay(path),
+        [command, period, date] if command == "history" => return history(period, date, config),
+        [command, path] if command == "repair" => return repair(path),
+        _ => (),
+    }
+
+    let mut counter = read_storage().unwrap_or_else(|err| {
+        //首次运行时没有存储文件
+        let not_found = err
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound);
+        if err.is::<NewerVersion>() {
+            alert!(format!("{}\n{err}", tr("alert.storage_newer")));
+        } else if !not_found {
+            alert!(format!("{}\n{err}", tr("alert.storage_damaged")));
+        }
+        Counter::new()
+    });
+    counter.set_config(config);
+    //上次没有正常保存时(崩溃等)累加之后记录的计数增量
+    let replayed = match journal::replay(&mut counter) {
+        Ok(count) => count,
+        Err(err) => {
+            eprintln!("回放增量日志失败: {err}");
+            0
+        }
+    };
 
-    unsafe {
-        COUNTER = Box::into_raw(counter);
+    if COUNTER.set(Mutex::new(counter)).is_err() {
+        return Err(anyhow!("计数器已经初始化"));
+    }
+    //累加后立即保存并删除日志, 避免日志一直增长到下次自动保存
+    if replayed > 0 {
+        println!("累加了增量日志中的{replayed}条记录");
+        if let Err(err) = journal::compact() {
+            eprintln!("保存数据失败: {err}");
+        }
     }
 
     window::open(get_counter().maps.len() == 0);
 
-    set_keyboard_hook(keyboard_hook_proc)?;
-    set_mouse_hook(mouse_hook_proc)?;
+    let (sender, receiver) = channel();
+    std::thread::spawn(move || input::consume(receiver));
+    input::default_source()?.run(sender)?;
+    Ok(())
+}
 
-    unsafe {
-        let mut msg = MSG::default();
-        while GetMessageW(&mut msg, HWND(0), 0, 0).0 > 0 {
-            TranslateMessage(&msg);
-            DispatchMessageW(&msg);
-        }
+/// 回放事件记录文件, 输出计数结果
+fn replay(path: &str) -> Result<()> {
+    let counter = Trace::load(path)?.replay();
+    print_counts(tr("cli.total"), &counter.maps);
+    print_counts(&counter.today.date, &counter.today.maps);
+    let keys = counter
+        .keys
+        .iter()
+        .map(|(code, count)| (keys::key_name(*code).to_string(), *count))
+        .collect();
+    print_counts(tr("cli.keys"), &keys);
+    let typing = &counter.typing;
+    println!("[{}]", tr("view.typing"));
+    println!(
+        "{}: {:.0}, {}: {:.0}, {}: {:.0}, {}: {:.0}",
+        tr("typing.average_kpm"),
+        typing.kpm(),
+        tr("typing.average_wpm"),
+        typing.wpm(),
+        tr("typing.peak_kpm"),
+        typing.peak_kpm,
+        tr("typing.peak_wpm"),
+        typing.peak_wpm
+    );
+    println!("[{}]", tr("cli.hold"));
+    for (label, count) in counter.hold.buckets() {
+        println!("{label}: {count}");
     }
+    println!("{}: {}ms", tr("cli.average"), counter.hold.average());
+    let corrections = &counter.corrections;
+    println!("[{}]", tr("correction.bursts"));
+    println!(
+        "{}: {}, {}: {:.1}, {}: {}",
+        tr("cli.count"),
+        corrections.bursts,
+        tr("cli.average"),
+        corrections.average_burst(),
+        tr("cli.longest"),
+        corrections.longest_burst
+    );
     Ok(())
 }
 
-unsafe extern "system" fn keyboard_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
-    let counter = get_counter_mut();
-    let data = lparam.0 as *const KBDLLHOOKSTRUCT;
-    if !data.is_null() {
-        let data: &KBDLLHOOKSTRUCT = &*data;
+/// 输出历史记录, `period`为day、week或month, `date`为`%Y-%m-%d`格式日期
+fn history(period: &str, date: &str, config: Config) -> Result<()> {
+    //今天的计数以及增量日志中还没有保存的计数也一起统计
+    let mut counter = read_storage()?;
+    counter.set_config(config);
+    journal::replay(&mut counter)?;
+    let now = counter.clock.now();
+    let today = std::mem::replace(&mut counter.today, Today::new(&now));
+    counter.history.archive(today);
 
-        match wparam {
-            WPARAM(0x100) => {
-                //普通键按下
-                counter.recv(Event::KeyEvent(KeyEvent::KeyPress(data.vkCode)));
-            }
-            WPARAM(0x101) => {
-                //普通键抬起
-                counter.recv(Event::KeyEvent(KeyEvent::KeyUp(data.vkCode)));
-            }
-            WPARAM(0x104) => {
-                //系统键按下
-                counter.recv(Event::KeyEvent(KeyEvent::KeyPress(data.vkCode)));
-            }
-            WPARAM(0x105) => {
-                //系统键抬起
-                counter.recv(Event::KeyEvent(KeyEvent::KeyUp(data.vkCode)));
-            }
-            _ => (),
-        };
-    }
-    CallNextHookEx(KEYBOARD_HOOK, code, wparam, lparam)
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")?;
+    let maps = match period {
+        "day" => counter
+            .history
+            .day(date)
+            .map(|day| day.maps.clone())
+            .unwrap_or_default(),
+        "week" => counter.history.week(date),
+        "month" => counter.history.month(date),
+        _ => return Err(anyhow!("不支持的统计周期: {period}")),
+    };
+    print_counts(&format!("{period} {date}"), &maps);
+    Ok(())
 }
 
-unsafe extern "system" fn mouse_hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
-    let counter = get_counter_mut();
+/// 从损坏的存储文件中恢复计数, 替换当前的存储文件(需要先退出程序)
+///
+/// 使用SQLite数据库时数据不保存在存储文件中, 不支持修复
+fn repair(path: &str) -> Result<()> {
+    if cfg!(feature = "sqlite") {
+        return Err(anyhow!(tr("cli.repair_sqlite")));
+    }
+    let counter = salvage(&std::fs::read(path)?)?;
+    print_counts(tr("cli.salvaged"), &counter.maps);
+    println!("{}: {}", tr("cli.history_days"), counter.history.days.len());
+    if let Some(moved) = replace_storage(&counter)? {
+        println!("{}: {}", tr("cli.moved"), moved.display());
+    }
+    println!("{}: {}", tr("cli.repaired"), get_storage_path().display());
+    Ok(())
+}
 
-    let data = lparam.0 as *const MSLLHOOKSTRUCT;
-    if !data.is_null() {
-        if let Ok(mouse_event) = MouseEvent::try_from(wparam.0 as i32) {
-            let point = Point {
-                x: (*data).pt.x,
-                y: (*data).pt.y,
-            };
-            let _ = counter.recv(Event::MouseEvent((mouse_event, point)));
-        }
+fn print_counts(title: &str, maps: &HashMap<String, u128>) {
+    println!("[{title}]");
+    let mut counts: Vec<_> = maps.iter().collect();
+    counts.sort();
+    for (name, count) in counts {
+        println!("{}: {count}", metric_label(name));
     }
-    CallNextHookEx(MOUSE_HOOK, code, wparam, lparam)
 }
@@ -0,0 +1,124 @@
+use anyhow::{anyhow, Result};
+
+use crate::chord::{is_modifier, Chord, Modifiers};
+
+/// 按键序列, 如`Esc Shift+; W Enter`、`Ctrl+K Ctrl+C`
+#[derive(Debug, Clone)]
+pub struct Sequence {
+    pub name: String,
+    steps: Vec<Chord>,
+    /// 相邻两步之间的最长间隔(毫秒)
+    timeout: i64,
+    /// 已匹配的每一步的输入, 不匹配时用于查找与开头重叠的部分
+    inputs: Vec<(Modifiers, u32)>,
+    /// 上一步匹配的时间戳(毫秒)
+    last_time: i64,
+}
+
+impl Sequence {
+    /// 解析空格分隔的每一步
+    pub fn parse(name: &str, steps: &str, timeout: i64) -> Result<Self> {
+        let steps = steps
+            .split_whitespace()
+            .map(|step| Chord::parse(step, step))
+            .collect::<Result<Vec<_>>>()?;
+        if steps.is_empty() {
+            return Err(anyhow!("按键序列为空"));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            steps,
+            timeout,
+            inputs: vec![],
+            last_time: 0,
+        })
+    }
+
+    /// 输入一次按下, 返回是否完成了整个序列
+    pub fn press(&mut self, modifiers: &Modifiers, code: u32, now: i64) -> bool {
+        if !self.inputs.is_empty() && now - self.last_time > self.timeout {
+            self.inputs.clear();
+        }
+        //按下组合键中的修饰键不打断序列
+        if !self.steps[self.inputs.len()].matches(modifiers, code) && is_modifier(code) {
+            return false;
+        }
+        //不匹配时去掉最早的输入, 直到剩下的输入与序列开头一致, 如`A A B`中输入`A A A B`
+        self.inputs.push((*modifiers, code));
+        while !self.matches_inputs() {
+            self.inputs.remove(0);
+        }
+        self.last_time = now;
+        if self.inputs.len() == self.steps.len() {
+            self.inputs.clear();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 已有的输入是否依次与序列开头的每一步匹配
+    fn matches_inputs(&self) -> bool {
+        self.inputs
+            .iter()
+            .zip(&self.steps)
+            .all(|((modifiers, code), step)| step.matches(modifiers, *code))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sequence;
+    use crate::chord::Modifiers;
+
+    const CTRL: u32 = 162;
+
+    /// 依次按下`(键码, 时间)`中的按键, 返回完成序列的次数
+    fn press(sequence: &mut Sequence, modifiers: &Modifiers, keys: &[(u32, i64)]) -> usize {
+        keys.iter()
+            .filter(|(code, now)| sequence.press(modifiers, *code, *now))
+            .count()
+    }
+
+    #[test]
+    fn partial_match_reset() {
+        let mut sequence = Sequence::parse("vim :w", "Esc W Enter", 1000).unwrap();
+        let none = Modifiers::default();
+        //中间按了其他键, 从头开始
+        let keys = [(0x1B, 0), (0x57, 10), (0x41, 20), (0x0D, 30)];
+        assert_eq!(press(&mut sequence, &none, &keys), 0);
+        let keys = [(0x1B, 40), (0x57, 50), (0x0D, 60)];
+        assert_eq!(press(&mut sequence, &none, &keys), 1);
+        //不匹配的按键是第一步时从这一步开始
+        let keys = [(0x1B, 70), (0x1B, 80), (0x57, 90), (0x0D, 100)];
+        assert_eq!(press(&mut sequence, &none, &keys), 1);
+    }
+
+    #[test]
+    fn overlapping_prefixes() {
+        let none = Modifiers::default();
+        let mut sequence = Sequence::parse("AAB", "A A B", 1000).unwrap();
+        let keys = [(0x41, 0), (0x41, 10), (0x41, 20), (0x42, 30)];
+        assert_eq!(press(&mut sequence, &none, &keys), 1);
+        let mut sequence = Sequence::parse("ABAC", "A B A C", 1000).unwrap();
+        let keys = [(0x41, 0), (0x42, 10), (0x41, 20), (0x42, 30)];
+        assert_eq!(press(&mut sequence, &none, &keys), 0);
+        let keys = [(0x41, 40), (0x43, 50)];
+        assert_eq!(press(&mut sequence, &none, &keys), 1);
+    }
+
+    #[test]
+    fn timeout_between_steps() {
+        let mut sequence = Sequence::parse("VS Code 注释", "Ctrl+K Ctrl+C", 1000).unwrap();
+        let mut ctrl = Modifiers::default();
+        ctrl.press(CTRL);
+        //按住Ctrl时的自动重复不打断序列, 间隔正好等于超时时间时仍然匹配
+        let keys = [(0x4B, 0), (CTRL, 500), (0x43, 1000)];
+        assert_eq!(press(&mut sequence, &ctrl, &keys), 1);
+        //超时后重新开始, 之后单独按Ctrl+C不完成序列
+        let keys = [(0x4B, 2000), (0x43, 3001), (0x43, 3500)];
+        assert_eq!(press(&mut sequence, &ctrl, &keys), 0);
+        let keys = [(0x4B, 4000), (0x43, 4500)];
+        assert_eq!(press(&mut sequence, &ctrl, &keys), 1);
+    }
+}
@@ -0,0 +1,367 @@
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Error, ErrorKind},
+    path::PathBuf,
+};
+
+use crate::{
+    counter::{Counter, Today},
+    history::History,
+    keys::key_name,
+    storage::{
+        backup, decode_day, decode_payload, set_read_only, FileStorage, Storage, STORAGE_VERSION,
+    },
+    tools::get_app_dir,
+};
+
+/// days和state表保存完整的数据, 数据格式与state中的版本对应, 其他表方便用SQL查询
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    version INTEGER NOT NULL,
+    data BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS days (
+    date TEXT PRIMARY KEY,
+    checksum INTEGER NOT NULL,
+    data BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS totals (
+    metric TEXT PRIMARY KEY,
+    count INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS daily_counts (
+    date TEXT NOT NULL,
+    metric TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (date, metric)
+);
+CREATE TABLE IF NOT EXISTS hourly_counts (
+    date TEXT NOT NULL,
+    hour INTEGER NOT NULL,
+    metric TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (date, hour, metric)
+);
+CREATE TABLE IF NOT EXISTS key_counts (
+    date TEXT NOT NULL,
+    key_code INTEGER NOT NULL,
+    key_name TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (date, key_code)
+);
+";
+
+/// 序列化除历史记录外的数据, 格式与相同版本存储文件的数据一致(历史记录为空), 用`decode_payload`读取
+///
+/// 字段顺序需要与Counter保持一致, bincode中嵌套元组的格式与展开的字段相同
+fn encode_state(counter: &Counter) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(&(
+        (
+            &counter.timestamp,
+            &counter.maps,
+            &counter.today,
+            &History::default(),
+            &counter.timeline,
+            &counter.keys,
+            &counter.clicks,
+            &counter.typing,
+            &counter.typing_session,
+            &counter.hold,
+            &counter.corrections,
+        ),
+        (
+            &counter.deletion_run,
+            &counter.last_mouse_click_event,
+            &counter.last_mouse_wheel_time,
+            &counter.last_mouse_move_time,
+            &counter.last_mouse_point,
+            &counter.mouse_distance,
+            &counter.last_active_time,
+            &counter.active_time,
+            &counter.show_today,
+            &counter.view,
+            &counter.keyboard_layout,
+            &counter.click_button,
+        ),
+    ))?)
+}
+
+pub fn get_database_path() -> PathBuf {
+    let mut app_dir = get_app_dir().unwrap_or(PathBuf::from("./"));
+    app_dir.push("keyboard-counter.db");
+    app_dir
+}
+
+/// SQLite数据库存储, 每次保存只写入有变化的日期
+pub struct SqliteStorage;
+
+impl SqliteStorage {
+    fn open(&self) -> Result<Connection> {
+        let conn = Connection::open(get_database_path())?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(conn)
+    }
+}
+
+/// 删除一天的数据
+fn delete_day(tx: &Transaction, date: &str) -> Result<()> {
+    for table in ["days", "daily_counts", "hourly_counts", "key_counts"] {
+        tx.execute(&format!("DELETE FROM {table} WHERE date = ?1"), [date])?;
+    }
+    Ok(())
+}
+
+/// 写入一天的数据, 替换这一天之前保存的数据
+fn write_day(tx: &Transaction, day: &Today, data: &[u8], checksum: i64) -> Result<()> {
+    delete_day(tx, &day.date)?;
+    for (metric, count) in &day.maps {
+        tx.execute(
+            "INSERT INTO daily_counts (date, metric, count) VALUES (?1, ?2, ?3)",
+            params![day.date, metric, i64::try_from(*count)?],
+        )?;
+    }
+    for (metric, hours) in &day.timeline.hours {
+        for (hour, count) in hours.iter().enumerate().filter(|(_, count)| **count > 0) {
+            tx.execute(
+                "INSERT INTO hourly_counts (date, hour, metric, count) VALUES (?1, ?2, ?3, ?4)",
+                params![day.date, hour, metric, i64::try_from(*count)?],
+            )?;
+        }
+    }
+    for (code, count) in &day.keys {
+        tx.execute(
+            "INSERT INTO key_counts (date, key_code, key_name, count) VALUES (?1, ?2, ?3, ?4)",
+            params![day.date, code, key_name(*code), i64::try_from(*count)?],
+        )?;
+    }
+    tx.execute(
+        "INSERT INTO days (date, checksum, data) VALUES (?1, ?2, ?3)",
+        params![day.date, checksum, data],
+    )?;
+    Ok(())
+}
+
+/// 读取state和每天的数据
+fn load_database(conn: &Connection) -> Result<Counter> {
+    let state: Option<(u32, Vec<u8>)> = conn
+        .query_row("SELECT version, data FROM state WHERE id = 1", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .optional()?;
+    //数据库已创建但还没有保存过数据, 与首次运行相同
+    let (version, data) = state.ok_or_else(|| Error::from(ErrorKind::NotFound))?;
+    //每天的数据与state的版本相同, 由decode_day按这个版本读取并升级,
+    //版本变化后第一次保存时全部按当前版本重新写入
+    let mut counter = decode_payload(version, &data)?;
+    let mut stmt = conn.prepare("SELECT date, data FROM days")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let date: String = row.get(0)?;
+        //今天的数据保存在state中
+        if date != counter.today.date {
+            let data: Vec<u8> = row.get(1)?;
+            let day = decode_day(version, &data).map_err(|err| anyhow!("{date}: {err}"))?;
+            counter.history.days.insert(date, day);
+        }
+    }
+    Ok(counter)
+}
+
+impl Storage for SqliteStorage {
+    /// 还没有数据库时读取存储文件, 下次保存时写入数据库
+    ///
+    /// 数据库无法读取时不再保存, 避免覆盖其中的数据
+    fn load(&self) -> Result<Counter> {
+        if !get_database_path().exists() {
+            return FileStorage.load();
+        }
+        load_database(&self.open()?).inspect_err(|err| {
+            let not_found = err
+                .downcast_ref::<Error>()
+                .is_some_and(|err| err.kind() == ErrorKind::NotFound);
+            if !not_found {
+                set_read_only();
+            }
+        })
+    }
+
+    /// 保存后按配置的间隔备份整个数据库, 备份失败不影响保存
+    fn save(&self, counter: &Counter) -> Result<()> {
+        let mut conn = self.open()?;
+        save_database(&mut conn, counter)?;
+        let result = backup(
+            "db",
+            counter.config.backup_interval,
+            counter.config.backup_count,
+            |path| {
+                conn.execute("VACUUM INTO ?1", [path.to_string_lossy()])?;
+                Ok(())
+            },
+        );
+        if let Err(err) = result {
+            eprintln!("备份失败: {err}");
+        }
+        Ok(())
+    }
+}
+
+/// 在一个事务中写入有变化的日期以及state、totals
+fn save_database(conn: &mut Connection, counter: &Counter) -> Result<()> {
+    let tx = conn.transaction()?;
+    let version: Option<u32> = tx
+        .query_row("SELECT version FROM state WHERE id = 1", [], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    let saved: HashMap<String, i64> = {
+        let mut stmt = tx.prepare("SELECT date, checksum FROM days")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    let days: Vec<&Today> = counter
+        .history
+        .days
+        .values()
+        .chain([&counter.today])
+        .collect();
+    //删除计数器中已经没有的日期(如清空数据后)
+    let dates: HashSet<&str> = days.iter().map(|day| day.date.as_str()).collect();
+    for date in saved.keys().filter(|date| !dates.contains(date.as_str())) {
+        delete_day(&tx, date)?;
+    }
+    //只写入有变化的日期, 版本变化后全部重新写入
+    for day in days {
+        let data = bincode::serialize(day)?;
+        let checksum = crc32fast::hash(&data) as i64;
+        if version != Some(STORAGE_VERSION) || saved.get(&day.date) != Some(&checksum) {
+            write_day(&tx, day, &data, checksum)?;
+        }
+    }
+    tx.execute("DELETE FROM totals", [])?;
+    for (metric, count) in &counter.maps {
+        tx.execute(
+            "INSERT INTO totals (metric, count) VALUES (?1, ?2)",
+            params![metric, i64::try_from(*count)?],
+        )?;
+    }
+    tx.execute(
+        "INSERT OR REPLACE INTO state (id, version, data) VALUES (1, ?1, ?2)",
+        params![STORAGE_VERSION, encode_state(counter)?],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{manual_counter, parse_time};
+
+    fn open_memory() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        conn
+    }
+
+    /// 2024-01-01和2024-01-02两天都有计数
+    fn two_days() -> Counter {
+        let (mut counter, clock) = manual_counter("2024-01-01T10:00:00+08:00");
+        counter.add_count("keystrokes");
+        clock.set(parse_time("2024-01-02T10:00:00+08:00"));
+        counter.add_count("keystrokes");
+        counter
+    }
+
+    fn daily_rows(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM daily_counts", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn state_matches_storage_payload() {
+        let mut counter = Counter::new();
+        counter.add_count("keystrokes");
+        counter.add_key(0x41);
+        let state = encode_state(&counter).unwrap();
+        //历史记录为空时与存储文件的数据完全相同
+        assert_eq!(state, bincode::serialize(&counter).unwrap());
+        let decoded = decode_payload(STORAGE_VERSION, &state).unwrap();
+        assert_eq!(decoded.maps, counter.maps);
+        assert_eq!(decoded.keys, counter.keys);
+    }
+
+    #[test]
+    fn clear_removes_saved_days() {
+        let mut conn = open_memory();
+        let mut counter = two_days();
+        save_database(&mut conn, &counter).unwrap();
+        assert_eq!(load_database(&conn).unwrap().history.days.len(), 1);
+        assert_eq!(daily_rows(&conn), 2);
+
+        counter.clear();
+        save_database(&mut conn, &counter).unwrap();
+        let loaded = load_database(&conn).unwrap();
+        assert!(loaded.history.days.is_empty());
+        assert!(loaded.maps.is_empty());
+        assert_eq!(daily_rows(&conn), 0);
+    }
+
+    #[test]
+    fn rewrite_day_without_count_change() {
+        let mut conn = open_memory();
+        let mut counter = two_days();
+        save_database(&mut conn, &counter).unwrap();
+        //计数不变, 只有按住时长变化
+        counter
+            .history
+            .days
+            .get_mut("2024-01-01")
+            .unwrap()
+            .hold
+            .add(300);
+        save_database(&mut conn, &counter).unwrap();
+        let loaded = load_database(&conn).unwrap();
+        assert_eq!(loaded.history.days["2024-01-01"].hold.count, 1);
+    }
+
+    #[test]
+    fn vacuum_into_copies_database() {
+        let mut conn = open_memory();
+        save_database(&mut conn, &two_days()).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("keyboard-counter-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        conn.execute("VACUUM INTO ?1", [path.to_string_lossy()])
+            .unwrap();
+        let copy = Connection::open(&path).unwrap();
+        assert_eq!(load_database(&copy).unwrap().history.days.len(), 1);
+        drop(copy);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn days_use_state_version() {
+        let mut conn = open_memory();
+        save_database(&mut conn, &two_days()).unwrap();
+        //每天的数据按state的版本读取, 版本不支持时整个数据库无法读取, 不会只读取一部分
+        conn.execute("UPDATE state SET version = 0", []).unwrap();
+        assert!(load_database(&conn).is_err());
+        conn.execute("UPDATE state SET version = ?1", [STORAGE_VERSION + 1])
+            .unwrap();
+        assert!(load_database(&conn).is_err());
+        conn.execute("UPDATE state SET version = ?1", [STORAGE_VERSION])
+            .unwrap();
+        assert_eq!(load_database(&conn).unwrap().history.days.len(), 1);
+    }
+
+    #[test]
+    fn reject_count_overflow() {
+        let mut conn = open_memory();
+        let mut counter = two_days();
+        counter.maps.insert("keystrokes".to_string(), u128::MAX);
+        assert!(save_database(&mut conn, &counter).is_err());
+    }
+}
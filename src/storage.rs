@@ -0,0 +1,552 @@
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use serde::de::DeserializeOwned;
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use crate::{
+    counter::{Counter, Today},
+    i18n::{tr, tr_args},
+    tools::get_app_dir,
+    typing::TypingSession,
+};
+
+/// 存储文件开头的标识, 没有标识的是加入版本号之前的文件(版本0)
+const MAGIC: &[u8; 4] = b"KBCT";
+/// 当前的存储格式版本, 修改Counter及其保存的字段后需要增加版本号,
+/// 并在legacy中冻结旧版本用到的所有结构, 在`decode_payload`和`decode_day`中都加入旧版本的升级
+pub const STORAGE_VERSION: u32 = 1;
+
+const BACKUP_PREFIX: &str = "keyboard-counter-";
+
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// 保存的数据无法读取又无法保留原数据时不再保存, 避免覆盖原有数据
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// 旧版本的存储结构, 只用于读取旧文件, 不要修改, 也不要引用会变化的结构
+mod legacy {
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    pub struct PointV0 {
+        pub x: i32,
+        pub y: i32,
+    }
+
+    #[derive(Deserialize)]
+    pub struct TodayV0 {
+        pub date: String,
+        pub maps: HashMap<String, u128>,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    pub struct CounterV0 {
+        pub timestamp: i64,
+        pub maps: HashMap<String, u128>,
+        pub today: TodayV0,
+        pub ctrl_press: bool,
+        pub alt_press: bool,
+        pub last_mouse_click_event: (i64, PointV0),
+        pub last_mouse_wheel_time: i64,
+        pub last_mouse_move_time: i64,
+        pub show_today: bool,
+    }
+}
+
+/// 版本0: 只有累计和今日计数, 计数名称为中文显示名称
+fn upgrade_v0(payload: &[u8]) -> Result<Counter> {
+    let old: legacy::CounterV0 = bincode::deserialize(payload)?;
+    let mut counter = Counter::new();
+    counter.timestamp = old.timestamp;
+    counter.maps = old.maps;
+    counter.today = Today {
+        date: old.today.date,
+        maps: old.today.maps,
+        ..Today::new(&counter.clock.now())
+    };
+    counter.show_today = old.show_today;
+    counter.migrate_metric_names();
+    Ok(counter)
+}
+
+/// 读取u32(小端), 数据不足时返回None
+fn read_u32(data: &[u8]) -> Option<(u32, &[u8])> {
+    let bytes = data.get(..4)?;
+    Some((u32::from_le_bytes(bytes.try_into().ok()?), &data[4..]))
+}
+
+/// 拆分文件头, 返回版本号、校验和(版本0没有)和序列化的数据
+fn split_header(data: &[u8]) -> Result<(u32, Option<u32>, &[u8])> {
+    let Some(rest) = data.strip_prefix(MAGIC) else {
+        return Ok((0, None, data));
+    };
+    let incomplete = || anyhow!(tr("error.storage_incomplete"));
+    let (version, rest) = read_u32(rest).ok_or_else(incomplete)?;
+    let (checksum, payload) = read_u32(rest).ok_or_else(incomplete)?;
+    Ok((version, Some(checksum), payload))
+}
+
+/// 数据由更高版本的程序保存, 不是损坏的数据, 不能移走或者用备份替换
+#[derive(Debug)]
+pub struct NewerVersion(pub u32);
+
+impl fmt::Display for NewerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = tr_args(
+            "error.storage_newer",
+            &[
+                ("version", &self.0.to_string()),
+                ("supported", &STORAGE_VERSION.to_string()),
+            ],
+        );
+        f.write_str(&text)
+    }
+}
+
+impl std::error::Error for NewerVersion {}
+
+/// 按版本解析数据, 旧版本升级到当前版本
+pub fn decode_payload(version: u32, payload: &[u8]) -> Result<Counter> {
+    match version {
+        0 => upgrade_v0(payload),
+        STORAGE_VERSION => {
+            let mut counter: Counter = bincode::deserialize(payload)?;
+            //上次运行时的鼠标位置, 不清空会把重启前后的位置差算作移动距离
+            counter.last_mouse_point = None;
+            Ok(counter)
+        }
+        _ => Err(NewerVersion(version).into()),
+    }
+}
+
+/// 按版本解析一天的数据, 用于按天保存历史记录的存储后端, 旧版本与`decode_payload`一样升级到当前版本
+#[cfg(feature = "sqlite")]
+pub fn decode_day(version: u32, payload: &[u8]) -> Result<Today> {
+    match version {
+        //版本0只有单个存储文件, 没有按天保存的数据
+        0 => Err(anyhow!(tr("error.no_daily_data_v0"))),
+        STORAGE_VERSION => Ok(bincode::deserialize(payload)?),
+        _ => Err(NewerVersion(version).into()),
+    }
+}
+
+/// 文件格式: 标识 + 版本号(u32小端) + 数据的CRC32校验和(u32小端) + bincode序列化的Counter
+pub fn encode(counter: &Counter) -> Result<Vec<u8>> {
+    let payload = bincode::serialize(counter)?;
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&STORAGE_VERSION.to_le_bytes());
+    data.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    data.extend_from_slice(&payload);
+    Ok(data)
+}
+
+/// 读取任意版本的文件, 校验和不一致时视为损坏
+pub fn decode(data: &[u8]) -> Result<Counter> {
+    let (version, checksum, payload) = split_header(data)?;
+    if let Some(checksum) = checksum {
+        if crc32fast::hash(payload) != checksum {
+            return Err(anyhow!(tr("error.storage_checksum")));
+        }
+    }
+    decode_payload(version, payload)
+}
+
+fn read<T: DeserializeOwned>(reader: &mut &[u8]) -> Option<T> {
+    bincode::deserialize_from(reader).ok()
+}
+
+/// 逐条读取HashMap或BTreeMap, 返回None时已读取的条目仍然保留
+fn read_entries<K: DeserializeOwned, V: DeserializeOwned>(
+    reader: &mut &[u8],
+    target: &mut impl Extend<(K, V)>,
+) -> Option<()> {
+    let len: u64 = read(reader)?;
+    for _ in 0..len {
+        target.extend(Some(read::<(K, V)>(reader)?));
+    }
+    Some(())
+}
+
+/// 按Counter的字段顺序逐个读取, 遇到无法解析的数据时停止, 字段顺序需要与Counter保持一致
+fn salvage_fields(reader: &mut &[u8], counter: &mut Counter) -> Option<()> {
+    counter.timestamp = read(reader)?;
+    read_entries::<String, u128>(reader, &mut counter.maps)?;
+    counter.today = read(reader)?;
+    read_entries::<String, Today>(reader, &mut counter.history.days)?;
+    counter.timeline = read(reader)?;
+    read_entries::<u32, u128>(reader, &mut counter.keys)?;
+    counter.clicks = read(reader)?;
+    counter.typing = read(reader)?;
+    //正在进行的打字不需要恢复
+    read::<Option<TypingSession>>(reader)?;
+    counter.hold = read(reader)?;
+    counter.corrections = read(reader)?;
+    Some(())
+}
+
+/// 版本0的字段顺序
+fn salvage_fields_v0(reader: &mut &[u8], counter: &mut Counter) -> Option<()> {
+    counter.timestamp = read(reader)?;
+    read_entries::<String, u128>(reader, &mut counter.maps)?;
+    let today: legacy::TodayV0 = read(reader)?;
+    counter.today.date = today.date;
+    counter.today.maps = today.maps;
+    Some(())
+}
+
+/// 从损坏的文件中尽量恢复计数, 忽略校验和, 无法完整解析时按字段顺序读取到损坏的位置为止
+pub fn salvage(data: &[u8]) -> Result<Counter> {
+    let (version, _, payload) = split_header(data)?;
+    let err = match decode_payload(version, payload) {
+        Ok(counter) => return Ok(counter),
+        Err(err) => err,
+    };
+    let mut counter = Counter::new();
+    let mut reader = payload;
+    match version {
+        0 => {
+            salvage_fields_v0(&mut reader, &mut counter);
+            counter.migrate_metric_names();
+        }
+        STORAGE_VERSION => {
+            salvage_fields(&mut reader, &mut counter);
+        }
+        _ => return Err(err),
+    }
+    if counter.maps.is_empty() && counter.history.days.is_empty() {
+        return Err(anyhow!(tr("error.nothing_to_salvage")));
+    }
+    Ok(counter)
+}
+
+pub fn get_storage_path() -> PathBuf {
+    let mut app_dir = get_app_dir().unwrap_or(PathBuf::from("./"));
+    app_dir.push("keyboard-counter.bin");
+    app_dir
+}
+
+/// 备份目录, 备份文件名为`keyboard-counter-时间.bin`, SQLite数据库的备份扩展名为`.db`
+fn get_backup_dir() -> PathBuf {
+    let mut backup_dir = get_app_dir().unwrap_or(PathBuf::from("./"));
+    backup_dir.push("backups");
+    let _ = fs::create_dir_all(&backup_dir);
+    backup_dir
+}
+
+/// 扩展名为`extension`的备份文件, 按时间从旧到新排列
+fn backups(extension: &str) -> Vec<PathBuf> {
+    let mut backups: Vec<PathBuf> = fs::read_dir(get_backup_dir())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension().is_some_and(|ext| ext == extension)
+                        && path
+                            .file_name()
+                            .and_then(|name| name.to_str())
+                            .is_some_and(|name| name.starts_with(BACKUP_PREFIX))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    backups.sort();
+    backups
+}
+
+/// 先写入临时文件并刷新到磁盘, 再替换原文件, 写入过程中崩溃不会损坏原文件
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp_path, path)?;
+    //重命名也需要刷新目录才能保证写入磁盘
+    #[cfg(unix)]
+    if let Some(dir) = path.parent() {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// 距离上次备份超过`interval`分钟时调用`write`写入新的备份, 只保留最新的`count`个备份
+pub fn backup(
+    extension: &str,
+    interval: i64,
+    count: usize,
+    write: impl FnOnce(&Path) -> Result<()>,
+) -> Result<()> {
+    if count == 0 {
+        return Ok(());
+    }
+    let due = match backups(extension).last() {
+        Some(last) => fs::metadata(last)?
+            .modified()?
+            .elapsed()
+            .map_or(true, |elapsed| elapsed.as_secs() as i64 >= interval * 60),
+        None => true,
+    };
+    if !due {
+        return Ok(());
+    }
+    let mut path = get_backup_dir();
+    path.push(format!(
+        "{BACKUP_PREFIX}{}.{extension}",
+        Local::now().format("%Y%m%d-%H%M%S")
+    ));
+    write(&path)?;
+    let backups = backups(extension);
+    for old in &backups[..backups.len().saturating_sub(count)] {
+        fs::remove_file(old)?;
+    }
+    Ok(())
+}
+
+/// 保存并按配置备份, 备份失败不影响保存
+fn write_storage(encoded: &[u8], backup_interval: i64, backup_count: usize) -> Result<()> {
+    //自动保存和退出时的保存可能同时进行, 共用同一个临时文件
+    let _lock = SAVE_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+    write_atomic(&get_storage_path(), encoded)?;
+    let result = backup("bin", backup_interval, backup_count, |path| {
+        write_atomic(path, encoded)
+    });
+    if let Err(err) = result {
+        eprintln!("备份失败: {err}");
+    }
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<Counter> {
+    let mut cfg_file = File::open(path)?;
+    let mut encoded = vec![];
+    cfg_file.read_to_end(&mut encoded)?;
+    decode(&encoded)
+}
+
+/// 将存储文件改名移到一边, 返回新的路径, 不会覆盖之前移走的文件
+fn quarantine(path: &Path) -> Result<PathBuf> {
+    let name = format!(
+        "keyboard-counter-damaged-{}",
+        Local::now().format("%Y%m%d-%H%M%S")
+    );
+    let target = (0..)
+        .map(|index| match index {
+            0 => path.with_file_name(format!("{name}.bin")),
+            _ => path.with_file_name(format!("{name}-{index}.bin")),
+        })
+        .find(|target| !target.exists())
+        .unwrap_or_default();
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+/// 存储后端
+pub trait Storage {
+    fn load(&self) -> Result<Counter>;
+
+    fn save(&self, counter: &Counter) -> Result<()>;
+}
+
+/// 保存为单个存储文件
+pub struct FileStorage;
+
+impl Storage for FileStorage {
+    /// 读取存储文件, 无法读取的文件移到一边避免被之后的保存覆盖, 然后从最新的有效备份恢复
+    ///
+    /// 更高版本的程序保存的文件不是损坏, 保留原文件并且不再保存, 避免降级使用时丢失数据
+    fn load(&self) -> Result<Counter> {
+        let path = get_storage_path();
+        let err = match read_file(&path) {
+            Ok(counter) => return Ok(counter),
+            Err(err) if !path.exists() => return Err(err),
+            Err(err) if err.is::<NewerVersion>() => {
+                set_read_only();
+                return Err(err);
+            }
+            Err(err) => err,
+        };
+        let error = err.to_string();
+        let err = match quarantine(&path) {
+            Ok(damaged) => {
+                let path = damaged.display().to_string();
+                anyhow!(tr_args(
+                    "error.storage_moved",
+                    &[("error", &error), ("path", &path)]
+                ))
+            }
+            Err(move_err) => {
+                set_read_only();
+                let reason = move_err.to_string();
+                anyhow!(tr_args(
+                    "error.storage_not_moved",
+                    &[("error", &error), ("reason", &reason)]
+                ))
+            }
+        };
+        for path in backups("bin").iter().rev() {
+            match read_file(path) {
+                Ok(counter) => {
+                    eprintln!("读取存储文件失败({err}), 已从备份{path:?}恢复");
+                    return Ok(counter);
+                }
+                Err(backup_err) => eprintln!("备份{path:?}无效: {backup_err}"),
+            }
+        }
+        Err(err)
+    }
+
+    fn save(&self, counter: &Counter) -> Result<()> {
+        let encoded: Vec<u8> = encode(counter)?;
+        write_storage(
+            &encoded,
+            counter.config.backup_interval,
+            counter.config.backup_count,
+        )
+    }
+}
+
+/// 启用sqlite功能时保存到SQLite数据库, 否则保存为存储文件
+fn backend() -> &'static dyn Storage {
+    #[cfg(feature = "sqlite")]
+    {
+        &crate::sqlite::SqliteStorage
+    }
+    #[cfg(not(feature = "sqlite"))]
+    {
+        &FileStorage
+    }
+}
+
+/// 读取失败且无法保留原数据时调用, 之后不再保存
+pub fn set_read_only() {
+    READ_ONLY.store(true, Ordering::Relaxed);
+}
+
+pub fn save_storage(data: &Counter) -> Result<()> {
+    if READ_ONLY.load(Ordering::Relaxed) {
+        return Err(anyhow!(tr("error.read_only")));
+    }
+    backend().save(data)
+}
+
+pub fn read_storage() -> Result<Counter> {
+    backend().load()
+}
+
+/// 用修复的数据替换保存的数据, 原有的存储文件移到一边, 返回其新的路径
+pub fn replace_storage(counter: &Counter) -> Result<Option<PathBuf>> {
+    let path = get_storage_path();
+    let moved = if path.exists() {
+        Some(quarantine(&path)?)
+    } else {
+        None
+    };
+    save_storage(counter)?;
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::clock::{manual_counter, parse_time};
+
+    fn counts(entries: &[(&str, u128)]) -> HashMap<String, u128> {
+        entries
+            .iter()
+            .map(|(name, count)| (name.to_string(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn upgrade_v0_file() {
+        //版本0的文件没有文件头, 字段顺序与legacy::CounterV0相同
+        let payload = bincode::serialize(&(
+            1_600_000_000_000i64,
+            counts(&[("键盘敲击", 120), ("鼠标左击", 30), ("Backspace", 5)]),
+            ("2020-09-13".to_string(), counts(&[("键盘敲击", 20)])),
+            false,
+            false,
+            (0i64, (0i32, 0i32)),
+            0i64,
+            0i64,
+            true,
+        ))
+        .unwrap();
+        let counter = decode(&payload).unwrap();
+        let maps = counts(&[
+            ("keystrokes", 120),
+            ("mouse_left_click", 30),
+            ("backspace", 5),
+        ]);
+        assert_eq!(counter.maps, maps);
+        assert_eq!(counter.today.date, "2020-09-13");
+        assert_eq!(counter.today.maps, counts(&[("keystrokes", 20)]));
+        assert!(counter.show_today);
+        //升级后按当前版本保存
+        let counter = decode(&encode(&counter).unwrap()).unwrap();
+        assert_eq!(counter.maps, maps);
+        assert_eq!(counter.today.maps, counts(&[("keystrokes", 20)]));
+    }
+
+    /// 有两天数据的计数器
+    fn sample() -> Counter {
+        let (mut counter, clock) = manual_counter("2024-01-01T10:00:00+08:00");
+        counter.add_count_by("keystrokes", 3);
+        counter.add_key(65);
+        clock.set(parse_time("2024-01-02T10:00:00+08:00"));
+        counter.add_count_by("mouse_distance", 42);
+        counter
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut data = encode(&sample()).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0x01;
+        let err = decode(&data).unwrap_err();
+        assert_eq!(err.to_string(), tr("error.storage_checksum"));
+    }
+
+    #[test]
+    fn mouse_point_not_restored() {
+        let mut counter = sample();
+        counter.last_mouse_point = Some(crate::counter::Point { x: 100, y: 200 });
+        let counter = decode(&encode(&counter).unwrap()).unwrap();
+        assert_eq!(counter.last_mouse_point, None);
+    }
+
+    #[test]
+    fn newer_version_is_not_damage() {
+        let mut data = encode(&sample()).unwrap();
+        data[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(STORAGE_VERSION + 1).to_le_bytes());
+        let err = decode(&data).unwrap_err();
+        assert!(err.is::<NewerVersion>());
+        assert!(salvage(&data).unwrap_err().is::<NewerVersion>());
+    }
+
+    #[test]
+    fn salvage_truncated() {
+        let counter = sample();
+        let mut data = encode(&counter).unwrap();
+        data.truncate(data.len() - 40);
+        assert!(decode(&data).is_err());
+        let salvaged = salvage(&data).unwrap();
+        assert_eq!(salvaged.maps, counter.maps);
+        assert_eq!(salvaged.keys, counter.keys);
+        assert_eq!(salvaged.history.days.len(), 1);
+    }
+}
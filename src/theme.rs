@@ -0,0 +1,117 @@
+use anyhow::Result;
+use std::{path::PathBuf, time::SystemTime};
+
+use crate::{config::Colors, tools::get_app_dir};
+
+/// 内置主题的名称和显示名称的翻译键
+pub const BUILTIN_THEMES: &[(&str, &str)] = &[
+    ("dark", "theme.dark"),
+    ("light", "theme.light"),
+    ("high-contrast", "theme.high-contrast"),
+];
+
+/// 使用配置文件中[colors]的主题
+pub const CUSTOM_THEME: &str = "custom";
+
+/// 内置主题的颜色
+pub fn builtin_theme(name: &str) -> Option<Colors> {
+    let [background, border, label, text, button_background, button_text] = match name {
+        "dark" => return Some(Colors::default()),
+        "light" => [
+            "#f3f3f3", "#a0a0a0", "#5a5a5a", "#1a1a1a", "#c8c8c8", "#333333",
+        ],
+        "high-contrast" => [
+            "#000000", "#ffffff", "#ffff00", "#ffffff", "#0000c0", "#ffffff",
+        ],
+        _ => return None,
+    };
+    Some(Colors {
+        background: background.to_string(),
+        border: border.to_string(),
+        label: label.to_string(),
+        text: text.to_string(),
+        button_background: button_background.to_string(),
+        button_text: button_text.to_string(),
+    })
+}
+
+/// 用户主题目录, 每个主题为一个与[colors]格式相同的toml文件
+pub fn get_themes_dir() -> PathBuf {
+    let mut themes_dir = get_app_dir().unwrap_or(PathBuf::from("./"));
+    themes_dir.push("themes");
+    themes_dir
+}
+
+/// 启动时创建主题目录
+pub fn create_themes_dir() -> Result<()> {
+    std::fs::create_dir_all(get_themes_dir())?;
+    Ok(())
+}
+
+/// 主题目录的修改时间, 增加、删除或重命名主题文件后变化
+pub fn themes_modified_time() -> Option<SystemTime> {
+    std::fs::metadata(get_themes_dir())
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// 主题目录中的主题名称(文件名), 与内置主题同名的无法选择, 不会列出
+pub fn user_themes() -> Vec<String> {
+    let mut themes: Vec<String> = std::fs::read_dir(get_themes_dir())
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+                .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+                .filter(|name| {
+                    let builtin = name == CUSTOM_THEME || builtin_theme(name).is_some();
+                    if builtin {
+                        eprintln!("主题{name}与内置主题同名, 请重命名主题文件");
+                    }
+                    !builtin
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    themes.sort();
+    themes
+}
+
+/// 加载内置主题或主题目录中的主题
+pub fn load_theme(name: &str) -> Result<Colors> {
+    if let Some(colors) = builtin_theme(name) {
+        return Ok(colors);
+    }
+    let mut path = get_themes_dir();
+    path.push(format!("{name}.toml"));
+    Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+/// 系统是否为深色模式
+#[cfg(windows)]
+pub fn system_dark_mode() -> Option<bool> {
+    use windows::{
+        w,
+        Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD},
+    };
+    let mut light: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            w!("Software\\Microsoft\\Windows\\CurrentVersion\\Themes\\Personalize"),
+            w!("AppsUseLightTheme"),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut light as *mut u32 as *mut std::ffi::c_void),
+            Some(&mut size),
+        )
+    };
+    result.is_ok().then_some(light == 0)
+}
+
+/// 其他平台无法获取系统主题
+#[cfg(not(windows))]
+pub fn system_dark_mode() -> Option<bool> {
+    None
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, FixedOffset, Timelike};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+
+use crate::counter::Counter;
+
+/// 按时间段统计的计数
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Timeline {
+    /// 每个指标在一天中每小时的计数
+    pub hours: HashMap<String, [u64; 24]>,
+    /// 每个指标每5分钟的计数, 键为一天中的第几个5分钟(0-287), 只保存有计数的时段, 配置开启时才统计
+    pub minutes: HashMap<String, BTreeMap<u16, u64>>,
+}
+
+impl Timeline {
+    /// `minutes`为是否同时统计5分钟的时段
+    pub fn add_count(
+        &mut self,
+        name: &str,
+        count: u128,
+        now: &DateTime<FixedOffset>,
+        minutes: bool,
+    ) {
+        let count = u64::try_from(count).unwrap_or(u64::MAX);
+        let hour = &mut self.hours.entry(name.to_string()).or_insert([0; 24])[now.hour() as usize];
+        *hour = hour.saturating_add(count);
+        if minutes {
+            let slot = (now.hour() * 12 + now.minute() / 5) as u16;
+            let minute = self
+                .minutes
+                .entry(name.to_string())
+                .or_default()
+                .entry(slot)
+                .or_insert(0);
+            *minute = minute.saturating_add(count);
+        }
+    }
+
+    pub fn merge(&mut self, other: Timeline) {
+        for (name, hours) in other.hours {
+            let total = self.hours.entry(name).or_insert([0; 24]);
+            for (total, count) in total.iter_mut().zip(hours) {
+                *total = total.saturating_add(count);
+            }
+        }
+        for (name, minutes) in other.minutes {
+            let total = self.minutes.entry(name).or_default();
+            for (slot, count) in minutes {
+                let total = total.entry(slot).or_insert(0);
+                *total = total.saturating_add(count);
+            }
+        }
+    }
+
+    /// 重命名指标, 新名称已有计数时累加
+    pub fn rename(&mut self, from: &str, to: &str) {
+        let mut renamed = Timeline::default();
+        if let Some(hours) = self.hours.remove(from) {
+            renamed.hours.insert(to.to_string(), hours);
+        }
+        if let Some(minutes) = self.minutes.remove(from) {
+            renamed.minutes.insert(to.to_string(), minutes);
+        }
+        self.merge(renamed);
+    }
+
+    fn write_csv(&self, date: &str, writer: &mut impl Write) -> Result<()> {
+        let mut names: Vec<_> = self.hours.keys().collect();
+        names.sort();
+        for name in names {
+            for (hour, count) in self.hours[name].iter().enumerate() {
+                if *count > 0 {
+                    writeln!(writer, "{date},{hour:02}:00,60,{name},{count}")?;
+                }
+            }
+            if let Some(minutes) = self.minutes.get(name) {
+                for (slot, count) in minutes {
+                    let (hour, minute) = (slot / 12, slot % 12 * 5);
+                    writeln!(writer, "{date},{hour:02}:{minute:02},5,{name},{count}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 导出历史记录和今天的时段计数
+///
+/// 每行为一个时段: 日期,开始时间,时长(分钟),指标,计数。配置开启5分钟时段时同时包含1小时和5分钟两种时段
+pub fn export_csv<P: AsRef<Path>>(counter: &Counter, path: P) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "date,start,minutes,metric,count")?;
+    for day in counter.history.days.values() {
+        day.timeline.write_csv(&day.date, &mut writer)?;
+    }
+    counter
+        .today
+        .timeline
+        .write_csv(&counter.today.date, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
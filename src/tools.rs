@@ -1,39 +1,43 @@
-use std::{
-    fs::File,
-    io::{Read, Write},
-    mem,
-    path::{Path, PathBuf},
-    thread::JoinHandle,
-};
+#[cfg(windows)]
+use std::{mem, path::Path};
+use std::{io::Write, path::PathBuf};
 
-use anyhow::{anyhow, Result};
+#[cfg(windows)]
+use anyhow::anyhow;
+use anyhow::Result;
 use bzip2::write::BzDecoder;
 use directories::ProjectDirs;
 use font_kit::font::Font;
 use minifb::Window;
 use pathfinder_geometry::vector::vec2f;
 use raqote::{DrawOptions, DrawTarget, Point, Source};
+#[cfg(windows)]
 use windows::Win32::{
     Foundation::{BOOL, HINSTANCE, HWND, LPARAM, LRESULT, MAX_PATH, WPARAM},
     UI::{
         Shell::{SHGetSpecialFolderPathW, CSIDL_STARTUP},
         WindowsAndMessaging::{
-            CreateIcon, GetDesktopWindow, SendMessageW, SetForegroundWindow, SetWindowsHookExW,
-            ShowWindow, UnhookWindowsHookEx, HHOOK, HICON, HOOKPROC, ICON_BIG, ICON_SMALL, SW_HIDE,
-            SW_SHOWNORMAL, WH_KEYBOARD_LL, WH_MOUSE_LL, WM_SETICON,
+            CreateIcon, GetDesktopWindow, IsWindowVisible, SendMessageW, SetForegroundWindow,
+            SetWindowsHookExW, ShowWindow, UnhookWindowsHookEx, HHOOK, HICON, HOOKPROC, ICON_BIG,
+            ICON_SMALL, SW_HIDE, SW_SHOWNORMAL, WH_KEYBOARD_LL, WH_MOUSE_LL, WM_SETICON,
         },
     },
 };
 
-use crate::counter::Counter;
+use crate::i18n::tr;
 
+#[cfg(windows)]
 type HookFn = unsafe extern "system" fn(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT;
 
+#[cfg(windows)]
 const PIXEL_SIZE: usize = 4;
 
+#[cfg(windows)]
 pub static mut MOUSE_HOOK: HHOOK = HHOOK(0);
+#[cfg(windows)]
 pub static mut KEYBOARD_HOOK: HHOOK = HHOOK(0);
 
+#[cfg(windows)]
 #[derive(Debug)]
 #[allow(dead_code)]
 pub(crate) struct Pixel {
@@ -43,6 +47,7 @@ pub(crate) struct Pixel {
     pub(crate) a: u8,
 }
 
+#[cfg(windows)]
 impl Pixel {
     fn convert_to_bgra(&mut self) {
         mem::swap(&mut self.r, &mut self.b);
@@ -58,6 +63,7 @@ pub fn load_tray_icon_from_memory(
     Ok(tray_icon::icon::Icon::from_rgba(rgba, width, height)?)
 }
 
+#[cfg(windows)]
 pub fn load_icon_from_memory(rgba: Vec<u8>, width: u32, height: u32) -> Result<HICON> {
     let rgba = decompress(&rgba)?;
 
@@ -85,6 +91,7 @@ pub fn load_icon_from_memory(rgba: Vec<u8>, width: u32, height: u32) -> Result<H
     Ok(handle)
 }
 
+#[cfg(windows)]
 pub fn set_window_icon(window: &Window, icon: HICON) -> Result<()> {
     let handle = HWND(window.get_window_handle() as isize);
     unsafe {
@@ -104,11 +111,17 @@ pub fn set_window_icon(window: &Window, icon: HICON) -> Result<()> {
     Ok(())
 }
 
+#[cfg(windows)]
 pub fn hide_window(window: &Window) -> BOOL {
     let handle = HWND(window.get_window_handle() as isize);
     unsafe { ShowWindow(handle, SW_HIDE) }
 }
 
+/// minifb没有提供隐藏窗口的接口, 其他平台不做处理
+#[cfg(not(windows))]
+pub fn hide_window(_window: &Window) {}
+
+#[cfg(windows)]
 pub fn show_window(window: &Window) {
     let handle = HWND(window.get_window_handle() as isize);
     //显示窗口
@@ -118,6 +131,22 @@ pub fn show_window(window: &Window) {
     }
 }
 
+#[cfg(not(windows))]
+pub fn show_window(_window: &Window) {}
+
+/// 窗口是否可见, 隐藏到托盘时不需要绘制
+#[cfg(windows)]
+pub fn is_window_visible(window: &Window) -> bool {
+    let handle = HWND(window.get_window_handle() as isize);
+    unsafe { IsWindowVisible(handle).as_bool() }
+}
+
+#[cfg(not(windows))]
+pub fn is_window_visible(_window: &Window) -> bool {
+    true
+}
+
+#[cfg(windows)]
 pub fn set_keyboard_hook(f: HookFn) -> Result<()> {
     unsafe {
         KEYBOARD_HOOK =
@@ -126,6 +155,7 @@ pub fn set_keyboard_hook(f: HookFn) -> Result<()> {
     Ok(())
 }
 
+#[cfg(windows)]
 pub fn set_mouse_hook(f: HookFn) -> Result<()> {
     unsafe {
         MOUSE_HOOK = SetWindowsHookExW(WH_MOUSE_LL, HOOKPROC::Some(f), HINSTANCE::default(), 0)?
@@ -133,12 +163,14 @@ pub fn set_mouse_hook(f: HookFn) -> Result<()> {
     Ok(())
 }
 
+#[cfg(windows)]
 pub fn remove_keyboard_hook() {
     unsafe {
         let _ = UnhookWindowsHookEx(KEYBOARD_HOOK);
     }
 }
 
+#[cfg(windows)]
 pub fn remove_mouse_hook() {
     unsafe {
         let _ = UnhookWindowsHookEx(MOUSE_HOOK);
@@ -163,44 +195,47 @@ pub fn get_app_dir() -> Result<PathBuf> {
     }
 }
 
-pub fn get_storage_path() -> String {
-    let mut app_dir = get_app_dir().unwrap_or(PathBuf::from("./"));
-    app_dir.push("keyboard-counter.bin");
-    app_dir.to_str().unwrap().to_string()
-}
-
-pub fn save_storage_async(data: &Counter) -> Result<JoinHandle<()>> {
-    let encoded: Vec<u8> = bincode::serialize(data)?;
-    Ok(std::thread::spawn(move || {
-        if let Ok(mut cfg_file) = File::create(&get_storage_path()) {
-            let res = cfg_file.write_all(&encoded);
-            println!("写入了配置文件:{:?}", res);
-        }
-    }))
-}
-
-pub fn save_storage(data: &Counter) -> Result<()> {
-    let mut cfg_file = File::create(&get_storage_path())?;
-    let encoded: Vec<u8> = bincode::serialize(data)?;
-    cfg_file.write_all(&encoded)?;
-    Ok(())
+/// 选择保存文件的路径, `file_type`为(名称, 通配符), 取消选择时返回None
+#[cfg(windows)]
+pub fn choose_save_path(
+    title: &str,
+    file_type: (&str, &str),
+    extension: &str,
+    file_name: &str,
+) -> Option<PathBuf> {
+    let params = wfd::DialogParams {
+        title,
+        file_types: vec![file_type],
+        default_extension: extension,
+        file_name,
+        ..Default::default()
+    };
+    wfd::save_dialog(params)
+        .ok()
+        .map(|dialog_result| dialog_result.selected_file_path)
 }
 
-pub fn read_storage() -> Result<Counter> {
-    let mut cfg_file = File::open(&get_storage_path())?;
-    let mut encoded = vec![];
-    cfg_file.read_to_end(&mut encoded)?;
-    let decoded: Counter = bincode::deserialize(&encoded[..])?;
-    // println!("读取到counter:{:?}", decoded);
-    Ok(decoded)
+/// 其他平台没有保存对话框, 直接保存到程序目录
+#[cfg(not(windows))]
+pub fn choose_save_path(
+    _title: &str,
+    _file_type: (&str, &str),
+    extension: &str,
+    file_name: &str,
+) -> Option<PathBuf> {
+    let mut path = get_app_dir().ok()?;
+    path.push(format!("{file_name}.{extension}"));
+    Some(path)
 }
 
+#[cfg(windows)]
 static TEMPLATE: &str = r"[InternetShortcut]
 URL=--
 IconIndex=0
 IconFile=--
 ";
 
+#[cfg(windows)]
 pub fn register_app_for_startup(app_name: &str) -> Result<()> {
     let hwnd = unsafe { GetDesktopWindow() };
     let mut path: [u16; MAX_PATH as usize] = [0; MAX_PATH as usize];
@@ -214,10 +249,11 @@ pub fn register_app_for_startup(app_name: &str) -> Result<()> {
         file.write_all(TEMPLATE.replace("--", exe_path).as_bytes())?;
         Ok(())
     } else {
-        Err(anyhow!("exe路径读取失败!"))
+        Err(anyhow!(tr("error.exe_path")))
     }
 }
 
+#[cfg(windows)]
 pub fn is_app_registered_for_startup(app_name: &str) -> Result<bool> {
     let hwnd = unsafe { GetDesktopWindow() };
     let mut path: [u16; MAX_PATH as usize] = [0; MAX_PATH as usize];
@@ -226,6 +262,7 @@ pub fn is_app_registered_for_startup(app_name: &str) -> Result<bool> {
     Ok(Path::new(&format!("{}\\{}.url", path, app_name)).exists())
 }
 
+#[cfg(windows)]
 pub fn remove_app_for_startup(app_name: &str) -> Result<()> {
     let hwnd = unsafe { GetDesktopWindow() };
     let mut path: [u16; MAX_PATH as usize] = [0; MAX_PATH as usize];
@@ -235,10 +272,36 @@ pub fn remove_app_for_startup(app_name: &str) -> Result<()> {
     Ok(())
 }
 
+#[cfg(not(windows))]
+pub fn register_app_for_startup(_app_name: &str) -> Result<()> {
+    Err(anyhow::anyhow!(tr("error.startup_unsupported")))
+}
+
+#[cfg(not(windows))]
+pub fn is_app_registered_for_startup(_app_name: &str) -> Result<bool> {
+    Ok(false)
+}
+
+#[cfg(not(windows))]
+pub fn remove_app_for_startup(_app_name: &str) -> Result<()> {
+    Err(anyhow::anyhow!(tr("error.startup_unsupported")))
+}
+
+#[macro_export]
+#[cfg(windows)]
+macro_rules! alert {
+    ($s:expr) => {{
+        let text = windows::core::HSTRING::from(AsRef::<str>::as_ref(&$s));
+        let title = windows::core::HSTRING::from($crate::i18n::tr("alert.title"));
+        unsafe { MessageBoxW(None, &text, &title, MB_OK) };
+    }};
+}
+
 #[macro_export]
+#[cfg(not(windows))]
 macro_rules! alert {
-    ($s:literal) => {{
-        unsafe { MessageBoxW(None, w!($s), w!("温馨提示"), MB_OK) };
+    ($s:expr) => {{
+        eprintln!("{}: {}", $crate::i18n::tr("alert.title"), $s);
     }};
 }
 
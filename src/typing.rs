@@ -0,0 +1,161 @@
+use serde::{Deserialize, Serialize};
+
+use crate::keys::VK_NUMPAD_ENTER;
+
+/// 一次打字持续超过这个时间(毫秒)才计入最高速度, 避免几次快速按键得到虚高的速度
+const PEAK_MIN_DURATION: i64 = 10 * 1000;
+
+const VK_SPACE: u32 = 0x20;
+const VK_ENTER: u32 = 0x0D;
+
+/// 空格和回车(包括小键盘Enter)作为单词分隔
+fn is_separator(code: u32) -> bool {
+    code == VK_SPACE || code == VK_ENTER || code == VK_NUMPAD_ENTER
+}
+
+/// 每分钟的数量, `duration`为毫秒
+fn per_minute(count: u64, duration: i64) -> f64 {
+    if duration > 0 {
+        count as f64 * 60. * 1000. / duration as f64
+    } else {
+        0.
+    }
+}
+
+/// 一次连续的打字, 从第一次按键开始, 按键间隔超过空闲时间结束, 空闲时间不计入打字时长
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct TypingSession {
+    /// 最后一次按键的时间戳(毫秒)
+    pub last_time: i64,
+    /// 第一次按键之后的按键数
+    pub keys: u64,
+    pub words: u64,
+    /// 打字时长(毫秒)
+    pub duration: i64,
+    last_is_separator: bool,
+}
+
+impl TypingSession {
+    pub fn new(code: u32, now: i64) -> Self {
+        Self {
+            last_time: now,
+            keys: 0,
+            words: 0,
+            duration: 0,
+            last_is_separator: is_separator(code),
+        }
+    }
+
+    /// `idle_time`为停止打字的按键间隔(毫秒)
+    pub fn is_active(&self, now: i64, idle_time: i64) -> bool {
+        now - self.last_time <= idle_time
+    }
+
+    /// 记录一次按键, 返回新增的单词数和时长
+    pub fn press(&mut self, code: u32, now: i64) -> (u64, i64) {
+        let duration = now - self.last_time;
+        let separator = is_separator(code);
+        let words = u64::from(separator && !self.last_is_separator);
+        self.last_time = now;
+        self.keys += 1;
+        self.words += words;
+        self.duration += duration;
+        self.last_is_separator = separator;
+        (words, duration)
+    }
+
+    pub fn kpm(&self) -> f64 {
+        per_minute(self.keys, self.duration)
+    }
+
+    pub fn wpm(&self) -> f64 {
+        per_minute(self.words, self.duration)
+    }
+}
+
+/// 打字速度统计, 平均速度只计算打字期间的时长
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct TypingStats {
+    pub keys: u64,
+    pub words: u64,
+    /// 打字时长(毫秒)
+    pub duration: i64,
+    pub peak_kpm: f64,
+    pub peak_wpm: f64,
+}
+
+impl TypingStats {
+    /// 记录一次按键, `session`为记录了本次按键的当前打字
+    pub fn add(&mut self, words: u64, duration: i64, session: &TypingSession) {
+        self.keys += 1;
+        self.words += words;
+        self.duration += duration;
+        if session.duration >= PEAK_MIN_DURATION {
+            self.peak_kpm = self.peak_kpm.max(session.kpm());
+            self.peak_wpm = self.peak_wpm.max(session.wpm());
+        }
+    }
+
+    pub fn merge(&mut self, other: TypingStats) {
+        self.keys += other.keys;
+        self.words += other.words;
+        self.duration += other.duration;
+        self.peak_kpm = self.peak_kpm.max(other.peak_kpm);
+        self.peak_wpm = self.peak_wpm.max(other.peak_wpm);
+    }
+
+    pub fn kpm(&self) -> f64 {
+        per_minute(self.keys, self.duration)
+    }
+
+    pub fn wpm(&self) -> f64 {
+        per_minute(self.words, self.duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::{
+        clock::{manual_counter, parse_time},
+        counter::{Event, KeyEvent},
+    };
+
+    const VK_A: u32 = 0x41;
+    const VK_SPACE: u32 = 0x20;
+
+    #[test]
+    fn idle_split_and_peak_duration() {
+        let start = parse_time("2024-01-01T10:00:00+08:00");
+        let (mut counter, clock) = manual_counter("2024-01-01T10:00:00+08:00");
+        let mut type_at = |millis: i64, code: u32| {
+            clock.set(start + Duration::milliseconds(millis));
+            counter.recv(Event::KeyEvent(KeyEvent::KeyPress(code)));
+            counter.recv(Event::KeyEvent(KeyEvent::KeyUp(code)));
+        };
+        //交替按A和空格, 每两次按键一个单词
+        let key = |index: i64| if index % 2 == 0 { VK_A } else { VK_SPACE };
+        //每秒一次按键, 持续11秒
+        for index in 0..12 {
+            type_at(index * 1000, key(index));
+        }
+        //停顿超过typing_idle_time(5秒)后重新开始, 停顿不计入时长
+        //第二次打字速度很快但不到10秒, 不计入最高速度
+        for index in 0..6 {
+            type_at(17000 + index * 100, key(index));
+        }
+
+        let typing = &counter.typing;
+        //每次打字的第一次按键不计数
+        assert_eq!(typing.keys, 11 + 5);
+        assert_eq!(typing.words, 6 + 3);
+        assert_eq!(typing.duration, 11000 + 500);
+        assert_eq!(typing.peak_kpm, 60.);
+        assert_eq!(typing.peak_wpm, 6. * 60. / 11.);
+        assert_eq!(counter.today.typing, counter.typing);
+        let session = counter.typing_session.as_ref().unwrap();
+        assert_eq!((session.keys, session.words, session.duration), (5, 3, 500));
+        assert_eq!(session.wpm(), 360.);
+    }
+}
@@ -1,26 +1,36 @@
 use anyhow::Result;
 use chrono::Utc;
-use font_kit::{family_name::FamilyName, properties::Properties, source::SystemSource};
-use minifb::{MouseButton, MouseMode, Window, WindowOptions};
-use raqote::{DrawOptions, DrawTarget, SolidSource, Source, StrokeStyle};
+use font_kit::{
+    family_name::FamilyName, font::Font, properties::Properties, source::SystemSource,
+};
+use minifb::{MenuHandle, MouseButton, MouseMode, Window, WindowOptions};
+use raqote::DrawTarget;
 use std::thread::JoinHandle;
 use tray_icon::{menu::Menu, tray_event_receiver, ClickEvent, TrayEvent, TrayIconBuilder};
-use wfd::DialogParams;
-use windows::{
-    w,
-    Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_OK},
-};
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_OK};
 
+#[cfg(windows)]
+use crate::tools::{
+    load_icon_from_memory, remove_keyboard_hook, remove_mouse_hook, set_window_icon,
+};
 use crate::{
     alert,
-    counter::DrawConfig,
-    get_counter, get_counter_mut,
+    config::{config_modified_time, read_config, write_config_value},
+    counter::{DrawConfig, View},
+    get_counter,
+    heatmap::{ClickButton, KeyboardLayout},
+    i18n::{language, set_language, tr, Language, LANGUAGES},
+    journal,
+    theme::{
+        create_themes_dir, system_dark_mode, themes_modified_time, user_themes, BUILTIN_THEMES,
+        CUSTOM_THEME,
+    },
     tools::{
-        hide_window, is_app_registered_for_startup, load_icon_from_memory,
-        load_tray_icon_from_memory, register_app_for_startup, remove_app_for_startup,
-        remove_keyboard_hook, remove_mouse_hook, save_storage, save_storage_async, set_window_icon,
-        show_window,
+        choose_save_path, hide_window, is_app_registered_for_startup, is_window_visible,
+        load_tray_icon_from_memory, register_app_for_startup, remove_app_for_startup, show_window,
     },
+    timeline::export_csv,
 };
 
 const ICON: &[u8] = include_bytes!("../icon.rgba.bzip2");
@@ -29,15 +39,274 @@ const ICON_SIZE: u32 = 128;
 pub const WIDTH: usize = 750;
 pub const HEIGHT: usize = 400;
 
-pub fn open(first_run: bool) -> JoinHandle<Result<()>> {
-    std::thread::spawn(move || run(first_run))
+/// 按顺序选择第一个已安装的字体
+fn load_font(families: &[String]) -> Result<Font> {
+    let mut families: Vec<FamilyName> = families
+        .iter()
+        .map(|family| FamilyName::Title(family.clone()))
+        .collect();
+    families.push(FamilyName::SansSerif);
+    Ok(SystemSource::new()
+        .select_best_match(&families, &Properties::default())?
+        .load()?)
+}
+
+/// 切换主题并保存到配置文件, 选择主题时不再跟随系统
+fn set_theme(theme: &str, follow_system_theme: bool) -> Result<()> {
+    write_config_value("theme", &toml::Value::String(theme.to_string()).to_string())?;
+    write_config_value("follow_system_theme", &follow_system_theme.to_string())?;
+    let mut counter = get_counter();
+    counter.config.theme = theme.to_string();
+    counter.config.follow_system_theme = follow_system_theme;
+    Ok(())
+}
+
+/// 切换界面语言并保存到配置文件
+fn set_ui_language(language: Language) -> Result<()> {
+    write_config_value(
+        "language",
+        &toml::Value::String(language.code().to_string()).to_string(),
+    )?;
+    get_counter().config.language = language;
+    set_language(language);
+    Ok(())
+}
+
+/// 添加窗口菜单, 切换语言后需要移除并重新添加
+fn add_menus(window: &mut Window, themes: &[String]) -> Result<Vec<MenuHandle>> {
+    let mut menu = minifb::Menu::new(tr("menu.settings"))?;
+    menu.add_item(tr("menu.startup"), 0).build();
+    menu.add_item(tr("menu.save_image"), 1).build();
+    menu.add_item(tr("menu.clear"), 2).build();
+    menu.add_item(tr("menu.export"), 3).build();
+    menu.add_item(tr("menu.save_keyboard_heatmap"), 4).build();
+    menu.add_item(tr("menu.save_click_heatmap"), 5).build();
+
+    //语言菜单从6开始
+    let mut language_menu = minifb::Menu::new(tr("menu.language"))?;
+    for (index, (_, name)) in LANGUAGES.iter().enumerate() {
+        language_menu.add_item(name, 6 + index).build();
+    }
+    menu.add_sub_menu(tr("menu.language"), &language_menu);
+
+    //主题菜单, 内置主题从40开始, 主题目录中的主题从50开始
+    let mut theme_menu = minifb::Menu::new(tr("menu.theme"))?;
+    for (index, (_, name)) in BUILTIN_THEMES.iter().enumerate() {
+        theme_menu.add_item(tr(name), 40 + index).build();
+    }
+    theme_menu.add_item(tr("theme.custom"), 43).build();
+    theme_menu.add_item(tr("theme.follow_system"), 44).build();
+    if !themes.is_empty() {
+        theme_menu.add_separator();
+    }
+    for (index, name) in themes.iter().enumerate() {
+        theme_menu.add_item(name, 50 + index).build();
+    }
+    menu.add_sub_menu(tr("menu.theme"), &theme_menu);
+
+    let mut view_menu = minifb::Menu::new(tr("menu.view"))?;
+    view_menu.add_item(tr("view.counts"), 10).build();
+    view_menu.add_item(tr("view.keyboard_heatmap"), 11).build();
+    view_menu.add_item(tr("view.click_heatmap"), 12).build();
+    view_menu.add_item(tr("view.typing"), 13).build();
+    view_menu.add_item(tr("view.corrections"), 14).build();
+    view_menu.add_separator();
+    view_menu.add_item(tr("layout.ansi"), 20).build();
+    view_menu.add_item(tr("layout.iso"), 21).build();
+    view_menu.add_item(tr("layout.tkl"), 22).build();
+    view_menu.add_separator();
+    view_menu.add_item(tr("clicks.all"), 30).build();
+    view_menu.add_item(tr("clicks.left"), 31).build();
+    view_menu.add_item(tr("clicks.right"), 32).build();
+    view_menu.add_item(tr("clicks.middle"), 33).build();
+
+    Ok(vec![window.add_menu(&menu), window.add_menu(&view_menu)])
+}
+
+/// 处理窗口菜单, 出错时由调用者提示
+fn menu_action(
+    menu_id: usize,
+    app_name: &str,
+    dt: &DrawTarget,
+    font: &Font,
+    draw_config: &mut DrawConfig<'static>,
+    themes: &[String],
+    dark_mode: Option<bool>,
+) -> Result<()> {
+    match menu_id {
+        0 => {
+            if is_app_registered_for_startup(app_name)? {
+                remove_app_for_startup(app_name)?;
+                alert!(tr("alert.startup_off"));
+            } else {
+                register_app_for_startup(app_name)?;
+                alert!(tr("alert.startup_on"));
+            }
+        }
+        1 => {
+            let date = chrono::Local::now();
+            let file_name = format!("{}-{}", tr("app.name"), date.format("%Y-%m-%d_%H-%M-%S"));
+            if let Some(save_path) =
+                choose_save_path(tr("menu.save_image"), ("PNG", "*.png"), "png", &file_name)
+            {
+                dt.write_png(save_path)?;
+            }
+        }
+        2 => {
+            get_counter().clear();
+            //数据无法保存(如只读)时提示, 重新启动后仍是原来的数据
+            if let Err(err) = journal::compact() {
+                alert!(format!("{}\n{err}", tr("alert.clear_not_saved")));
+            }
+        }
+        3 => {
+            let date = chrono::Local::now();
+            let file_name = format!("{}-{}", tr("app.name"), date.format("%Y-%m-%d_%H-%M-%S"));
+            if let Some(save_path) =
+                choose_save_path(tr("menu.export"), ("CSV", "*.csv"), "csv", &file_name)
+            {
+                //复制数据后写入文件, 写入时不影响计数
+                let counter = get_counter().clone();
+                export_csv(&counter, save_path)?;
+            }
+        }
+        4 => {
+            let date = chrono::Local::now();
+            let file_name = format!(
+                "{}-{}",
+                tr("file.keyboard_heatmap"),
+                date.format("%Y-%m-%d_%H-%M-%S")
+            );
+            if let Some(save_path) = choose_save_path(
+                tr("menu.save_keyboard_heatmap"),
+                ("PNG", "*.png"),
+                "png",
+                &file_name,
+            ) {
+                //单独以两倍大小绘制, 不包含底部按钮
+                let (width, height) = (WIDTH as i32 * 2, HEIGHT as i32 * 2);
+                let mut heatmap = DrawTarget::new(width, height);
+                heatmap.fill_rect(
+                    0.,
+                    0.,
+                    width as f32,
+                    height as f32,
+                    &draw_config.background,
+                    &draw_config.draw_options,
+                );
+                let counter = get_counter().view_snapshot(View::KeyboardHeatmap);
+                counter.draw_keyboard_heatmap(
+                    &mut heatmap,
+                    font,
+                    draw_config,
+                    20.,
+                    20.,
+                    width as f32 - 40.,
+                    height as f32 - 40.,
+                );
+                heatmap.write_png(save_path)?;
+            }
+        }
+        5 => {
+            let date = chrono::Local::now();
+            let file_name = format!(
+                "{}-{}",
+                tr("file.click_heatmap"),
+                date.format("%Y-%m-%d_%H-%M-%S")
+            );
+            if let Some(save_path) = choose_save_path(
+                tr("menu.save_click_heatmap"),
+                ("PNG", "*.png"),
+                "png",
+                &file_name,
+            ) {
+                //按屏幕范围的比例绘制, 长边固定为1920, 避免范围异常时图片过大
+                let counter = get_counter().view_snapshot(View::ClickHeatmap);
+                let bounds = counter.click_bounds().unwrap_or((0, 0, 1919, 1079));
+                let (bounds_width, bounds_height) = (
+                    (bounds.2 as f32 - bounds.0 as f32 + 1.).max(1.),
+                    (bounds.3 as f32 - bounds.1 as f32 + 1.).max(1.),
+                );
+                let scale = 1920. / bounds_width.max(bounds_height);
+                let width = (bounds_width * scale).ceil().max(1.) as i32;
+                let height = (bounds_height * scale).ceil().max(1.) as i32;
+                let mut heatmap = DrawTarget::new(width, height);
+                heatmap.fill_rect(
+                    0.,
+                    0.,
+                    width as f32,
+                    height as f32,
+                    &draw_config.background,
+                    &draw_config.draw_options,
+                );
+                counter.draw_click_heatmap(
+                    &mut heatmap,
+                    draw_config,
+                    0.,
+                    0.,
+                    width as f32,
+                    height as f32,
+                );
+                heatmap.write_png(save_path)?;
+            }
+        }
+        6..=7 => set_ui_language(LANGUAGES[menu_id - 6].0)?,
+        10 => get_counter().view = View::Counts,
+        11 => get_counter().view = View::KeyboardHeatmap,
+        12 => get_counter().view = View::ClickHeatmap,
+        13 => get_counter().view = View::Typing,
+        14 => get_counter().view = View::Corrections,
+        20 => get_counter().keyboard_layout = KeyboardLayout::Ansi,
+        21 => get_counter().keyboard_layout = KeyboardLayout::Iso,
+        22 => get_counter().keyboard_layout = KeyboardLayout::Tkl,
+        30 => get_counter().click_button = None,
+        31 => get_counter().click_button = Some(ClickButton::Left),
+        32 => get_counter().click_button = Some(ClickButton::Right),
+        33 => get_counter().click_button = Some(ClickButton::Middle),
+        40..=42 => {
+            set_theme(BUILTIN_THEMES[menu_id - 40].0, false)?;
+            *draw_config = get_counter().config.draw_config();
+        }
+        43 => {
+            set_theme(CUSTOM_THEME, false)?;
+            *draw_config = get_counter().config.draw_config();
+        }
+        44 => {
+            let theme = get_counter().config.theme.clone();
+            set_theme(&theme, true)?;
+            *draw_config = get_counter().config.draw_config();
+            if dark_mode.is_none() {
+                alert!(tr("alert.follow_system_unsupported"));
+            }
+        }
+        id if id >= 50 && id - 50 < themes.len() => {
+            set_theme(&themes[id - 50], false)?;
+            *draw_config = get_counter().config.draw_config();
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// 在新线程中打开窗口, 窗口出错退出时提示并保存后结束程序, 避免继续计数却不再保存
+pub fn open(first_run: bool) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        if let Err(err) = run(first_run) {
+            alert!(format!("{}\n{err}", tr("alert.window_failed")));
+            if let Err(err) = journal::compact() {
+                eprintln!("保存数据失败: {err}");
+            }
+            std::process::exit(1);
+        }
+    })
 }
 
 pub fn run(mut first_run: bool) -> Result<()> {
+    //开机启动使用的名称, 不随语言变化
     let app_name = "按键统计";
 
     let mut window = Window::new(
-        app_name,
+        tr("app.name"),
         WIDTH,
         HEIGHT,
         WindowOptions {
@@ -47,62 +316,31 @@ pub fn run(mut first_run: bool) -> Result<()> {
         },
     )?;
 
-    let mut menu = minifb::Menu::new("设置")?;
-    menu.add_item("开机启动", 0).build();
-    menu.add_item("保存图片", 1).build();
-    menu.add_item("清空数据", 2).build();
-    window.add_menu(&menu);
+    if let Err(err) = create_themes_dir() {
+        eprintln!("创建主题目录失败: {err}");
+    }
+    let mut themes_time = themes_modified_time();
+    let mut themes = user_themes();
+    let mut menus = add_menus(&mut window, &themes)?;
+    let mut menu_language = language();
 
     // Limit to max ~60 fps update rate
     window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
 
-    let properties = Properties::default();
-
-    let font = SystemSource::new()
-        .select_best_match(
-            &vec![
-                FamilyName::Title("微软雅黑".to_string()),
-                FamilyName::Title("Tahoma".to_string()),
-            ],
-            &properties,
-        )?
-        .load()?;
-
-    let draw_config = DrawConfig {
-        background: Source::Solid(SolidSource::from_unpremultiplied_argb(
-            0xFF, 0x1a, 0x21, 0x2c,
-        )),
-        border_color: Source::Solid(SolidSource::from_unpremultiplied_argb(
-            0xFF, 0x70, 0x70, 0x70,
-        )),
-        label_color: Source::Solid(SolidSource::from_unpremultiplied_argb(
-            0xFF, 0xad, 0xad, 0xad,
-        )),
-        text_color: Source::Solid(SolidSource::from_unpremultiplied_argb(
-            0xFF, 0xF1, 0xF1, 0xF1,
-        )),
-        button_background: Source::Solid(SolidSource::from_unpremultiplied_argb(
-            0xFF, 0x70, 0x70, 0x70,
-        )),
-        button_text_color: Source::Solid(SolidSource::from_unpremultiplied_argb(
-            0xFF, 0xcd, 0xcd, 0xcd,
-        )),
-        stroke_style: StrokeStyle {
-            width: 1.,
-            ..Default::default()
-        },
-        draw_options: DrawOptions::default(),
-        lable_font_size: 20.,
-        font_size: 24.,
-    };
+    let mut font = load_font(&get_counter().config.fonts)?;
+    let mut draw_config = get_counter().config.draw_config();
 
     let size = window.get_size();
     let mut dt = DrawTarget::new(size.0 as i32, size.1 as i32);
 
+    //Linux下托盘菜单由GTK创建, 需要先在当前线程初始化GTK
+    #[cfg(target_os = "linux")]
+    gtk::init()?;
+
     let tray_menu = Menu::new();
     let _tray_icon = TrayIconBuilder::new()
         .with_menu(Box::new(tray_menu))
-        .with_tooltip(app_name)
+        .with_tooltip(tr("app.name"))
         .with_icon(load_tray_icon_from_memory(
             ICON.to_vec(),
             ICON_SIZE,
@@ -112,37 +350,112 @@ pub fn run(mut first_run: bool) -> Result<()> {
 
     let mut active = true;
 
+    #[cfg(windows)]
     set_window_icon(
         &window,
         load_icon_from_memory(ICON.to_vec(), ICON_SIZE, ICON_SIZE)?,
     )?;
 
     let mut last_save_time = Utc::now().timestamp_millis();
+    let mut saving: Option<JoinHandle<()>> = None;
+    let mut last_config_check_time = last_save_time;
+    let mut config_time = config_modified_time();
+    let mut dark_mode = system_dark_mode();
+    //上一次绘制时的计数和显示设置, 字体、颜色或语言变化后清空以重新绘制
+    let mut drawn_state = None;
+    let mut last_draw_time = 0;
 
     while window.is_open() {
-        //每隔1分钟存盘
+        //定时在后台存盘, 存盘后清空增量日志, 上一次还没有保存完时不重复保存
         let now = Utc::now().timestamp_millis();
-        if now - last_save_time > 60 * 1000 {
-            let _ = save_storage_async(get_counter());
+        if now - last_save_time > get_counter().config.autosave_interval * 1000 {
+            if !saving.as_ref().is_some_and(|handle| !handle.is_finished()) {
+                saving = Some(journal::compact_async());
+            }
             last_save_time = now;
         }
 
+        //每秒写入增量日志, 检查配置文件, 修改后重新加载
+        let mut rebuild_menus = false;
+        if now - last_config_check_time > 1000 {
+            last_config_check_time = now;
+            if let Err(err) = journal::flush() {
+                eprintln!("写入增量日志失败: {err}");
+            }
+            let modified_time = config_modified_time();
+            if modified_time != config_time {
+                config_time = modified_time;
+                match read_config() {
+                    Ok(config) => {
+                        if config.fonts != get_counter().config.fonts {
+                            match load_font(&config.fonts) {
+                                Ok(new_font) => font = new_font,
+                                Err(err) => eprintln!("加载字体失败: {err}"),
+                            }
+                        }
+                        draw_config = config.draw_config();
+                        get_counter().set_config(config);
+                    }
+                    Err(err) => eprintln!("读取配置文件失败: {err}"),
+                }
+            }
+            //跟随系统时检查系统主题是否变化
+            let new_dark_mode = system_dark_mode();
+            if new_dark_mode != dark_mode {
+                dark_mode = new_dark_mode;
+                draw_config = get_counter().config.draw_config();
+                drawn_state = None;
+            }
+            //主题目录变化后重新查找主题, 主题增减时重新生成主题菜单, 当前主题被删除时改用默认颜色
+            let modified_time = themes_modified_time();
+            if modified_time != themes_time {
+                themes_time = modified_time;
+                let new_themes = user_themes();
+                if new_themes != themes {
+                    themes = new_themes;
+                    rebuild_menus = true;
+                    draw_config = get_counter().config.draw_config();
+                    drawn_state = None;
+                }
+            }
+        }
+
+        //语言或主题列表变化后重新生成菜单和标题
+        if rebuild_menus || language() != menu_language {
+            menu_language = language();
+            for menu in menus.drain(..) {
+                window.remove_menu(menu);
+            }
+            menus = add_menus(&mut window, &themes)?;
+            window.set_title(tr("app.name"));
+            drawn_state = None;
+        }
+
         if !first_run {
             hide_window(&window);
             first_run = true;
         }
         let (width, height) = window.get_size();
 
-        if active || width * height > 0 {
-            //渲染
-            get_counter().draw(&mut dt, &font, &draw_config);
+        //窗口可见时, 计数或显示设置变化后才重新绘制, 每秒至少绘制一次以更新当前打字速度
+        if is_window_visible(&window) && width * height > 0 {
+            let counter = get_counter();
+            let state = counter.draw_state();
+            if drawn_state != Some(state) || now - last_draw_time > 1000 {
+                //复制数据后渲染, 渲染时不影响计数
+                let snapshot = counter.draw_snapshot();
+                drop(counter);
+                snapshot.draw(&mut dt, &font, &draw_config);
+                drawn_state = Some(state);
+                last_draw_time = now;
+            }
         }
 
         if window.is_key_down(minifb::Key::Left) {
-            get_counter_mut().show_today = false;
+            get_counter().show_today = false;
         }
         if window.is_key_down(minifb::Key::Right) {
-            get_counter_mut().show_today = true;
+            get_counter().show_today = true;
         }
 
         if window.get_mouse_down(MouseButton::Left) {
@@ -157,11 +470,11 @@ pub fn run(mut first_run: bool) -> Result<()> {
                 let h = 60.;
 
                 if x > x1 && x < x1 + w && y > y1 && y < y1 + h {
-                    get_counter_mut().show_today = false;
+                    get_counter().show_today = false;
                 }
 
                 if x > x2 && x < x2 + w && y > y2 && y < y2 + h {
-                    get_counter_mut().show_today = true;
+                    get_counter().show_today = true;
                 }
             }
         }
@@ -178,43 +491,28 @@ pub fn run(mut first_run: bool) -> Result<()> {
 
         if let Some(menu_id) = window.is_menu_pressed() {
             // println!("菜单点击:{}", menu_id);
-            match menu_id {
-                0 => {
-                    if is_app_registered_for_startup(app_name)? {
-                        remove_app_for_startup(app_name)?;
-                        alert!("已关闭开机启动！");
-                    } else {
-                        register_app_for_startup(app_name)?;
-                        alert!("已设置开机启动！");
-                    }
-                }
-                1 => {
-                    let date = chrono::Local::now();
-                    let file_name = format!("{}-{}", app_name, date.format("%Y-%m-%d_%H-%M-%S"));
-                    let params = DialogParams {
-                        title: "保存图片",
-                        file_types: vec![("PNG", "*.png")],
-                        default_extension: "png",
-                        file_name: &file_name,
-                        ..Default::default()
-                    };
-                    if let Ok(dialog_result) = wfd::save_dialog(params) {
-                        let save_path = dialog_result
-                            .selected_file_path
-                            .to_str()
-                            .unwrap_or(&file_name);
-                        dt.write_png(save_path)?;
-                    }
-                }
-                2 => {
-                    get_counter_mut().clear();
-                }
-                _ => (),
+            if let Err(err) = menu_action(
+                menu_id,
+                app_name,
+                &dt,
+                &font,
+                &mut draw_config,
+                &themes,
+                dark_mode,
+            ) {
+                alert!(format!("{}\n{err}", tr("alert.menu_failed")));
             }
+            drawn_state = None;
         }
 
         window.update_with_buffer(dt.get_data(), size.0, size.1)?;
 
+        //Linux下托盘图标和菜单依靠GTK的事件循环
+        #[cfg(target_os = "linux")]
+        while gtk::events_pending() {
+            gtk::main_iteration_do(false);
+        }
+
         //处理托盘事件
         match tray_event_receiver().try_recv() {
             Ok(TrayEvent { event, .. }) => match event {
@@ -229,10 +527,16 @@ pub fn run(mut first_run: bool) -> Result<()> {
         };
     }
 
-    remove_keyboard_hook();
-    remove_mouse_hook();
-    //存盘
-    save_storage(get_counter())?;
+    #[cfg(windows)]
+    {
+        remove_keyboard_hook();
+        remove_mouse_hook();
+    }
+    //等待后台存盘结束后存盘
+    if let Some(handle) = saving.take() {
+        let _ = handle.join();
+    }
+    journal::compact()?;
     //退出
     std::process::exit(0);
 }